@@ -0,0 +1,137 @@
+//! AsciiDoc formatted changelog
+
+use crate::changelog::{self, Position};
+
+/// Default insertion marker, comment line isn't rendered
+pub const COMMENT: &str = "// bureaucrate goes here";
+pub const FILE_NAME: &str = "CHANGELOG.adoc";
+
+/// Convert markdown headings of generator output to AsciiDoc ones, one level
+/// down, so they are nested under `== vX.Y.Z` release heading.
+///
+/// Lines inside of fenced code blocks are kept as-is
+pub fn convert_headings(changelog: &str) -> String {
+    let mut out = String::new();
+    let mut fence = None;
+    for line in changelog.lines() {
+        let trimmed = line.trim_start();
+        let level = line.chars().take_while(|c| *c == '#').count();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") {
+            fence = Some("```");
+        } else if trimmed.starts_with("~~~") {
+            fence = Some("~~~");
+        } else if (1..=5).contains(&level)
+            && matches!(line[level..].chars().next(), Some(' ' | '\t'))
+        {
+            out.push_str(&"=".repeat(level + 1));
+            out.push_str(&line[level..]);
+            out.push('\n');
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Release heading, version is linked to `url` if present
+pub fn heading(label: &str, date: &str, url: Option<&str>) -> String {
+    match url {
+        Some(url) => format!("== {url}[{label}] {date}"),
+        None => format!("== {label} {date}"),
+    }
+}
+
+/// Label of release heading, created by [`heading`]
+fn heading_label(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("== ")?;
+    let version = rest.split_whitespace().next()?;
+    Some(match version.split_once('[') {
+        Some((_url, label)) => label.strip_suffix(']')?,
+        None => version,
+    })
+}
+
+/// Same as [`changelog::find_release`], but for `== ` headings
+pub fn find_release(old: &str, marker: &str, label: &str, position: Position) -> Option<bool> {
+    let labels: Vec<_> = position
+        .releases(old, marker)
+        .lines()
+        .filter_map(heading_label)
+        .collect();
+    changelog::is_latest(&labels, label, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_outside_of_code_blocks() {
+        let changelog = "# Fixed\n\n\
+            ## Parser\n\n\
+            ### Tags\n\n\
+            #### Sorting\n\n\
+            ##### Details\n\n\
+            ###### Too deep\n\n\
+            - Attributes are parsed:\n\n\
+            ```rust\n\
+            # hidden doc line\n\
+            ```\n\n\
+            ~~~\n\
+            ## not a heading\n\
+            ~~~\n\
+            #hashtag\n";
+        assert_eq!(
+            convert_headings(changelog),
+            "== Fixed\n\n\
+            === Parser\n\n\
+            ==== Tags\n\n\
+            ===== Sorting\n\n\
+            ====== Details\n\n\
+            ###### Too deep\n\n\
+            - Attributes are parsed:\n\n\
+            ```rust\n\
+            # hidden doc line\n\
+            ```\n\n\
+            ~~~\n\
+            ## not a heading\n\
+            ~~~\n\
+            #hashtag\n"
+        );
+    }
+
+    #[test]
+    fn release_heading_label() {
+        let linked = heading(
+            "v1.0.0",
+            "2024-06-01",
+            Some("https://example.com/compare/v0.1.0...v1.0.0"),
+        );
+        assert_eq!(
+            linked,
+            "== https://example.com/compare/v0.1.0...v1.0.0[v1.0.0] 2024-06-01"
+        );
+        assert_eq!(heading_label(&linked), Some("v1.0.0"));
+        assert_eq!(
+            heading_label(&heading("v1.0.0", "2024-06-01", None)),
+            Some("v1.0.0")
+        );
+        assert_eq!(heading_label("=== Fixed"), None);
+
+        let old = format!("= Changelog\n\n{COMMENT}\n\n== v1.1.0 2024-07-01\n\n{linked}\n");
+        let marker = format!("{COMMENT}\n");
+        assert_eq!(
+            find_release(&old, &marker, "v1.1.0", Position::Top),
+            Some(true)
+        );
+        assert_eq!(
+            find_release(&old, &marker, "v1.0.0", Position::Top),
+            Some(false)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! Crash-safe file writes
+
+use std::{
+    fs::{self, File},
+    io::Write,
+};
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8Path;
+
+/// Write `contents` to temporary file next to `path`, then rename it into place,
+/// so `path` either has old or new contents even if process is interrupted.
+///
+/// `fs::rename` replaces existing file on all platforms, including Windows
+pub fn write(path: &Utf8Path, contents: &str) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{path} is not a file"))?;
+    let tmp = path.with_file_name(format!(".{file_name}.bureaucrate-tmp"));
+    let result = (|| -> Result<()> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        // Keep permissions of replaced file
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("failed to write {path}"))
+}
@@ -1,8 +1,85 @@
-use jrsonnet_evaluator::typed::BoundedI8;
+use std::str::FromStr;
+
+use anyhow::bail;
+use clap::ValueEnum;
+use jrsonnet_evaluator::Val;
 use semver::{BuildMetadata, Prerelease, Version};
+use serde::{Deserialize, Serialize};
+
+/// How bump is applied to version, defaults match CLI ones
+#[derive(Default)]
+pub struct BumpPolicy {
+    /// Major bump of `0.x` version produces `1.0.0`
+    pub promote_major: bool,
+    pub prerelease: PrereleasePolicy,
+    pub build_metadata: BuildMetadataPolicy,
+}
+
+/// What to do with build metadata of bumped version
+#[derive(Clone, Default)]
+pub enum BuildMetadataPolicy {
+    Keep,
+    #[default]
+    Strip,
+    Set(BuildMetadata),
+}
+impl FromStr for BuildMetadataPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "keep" => Self::Keep,
+            "strip" => Self::Strip,
+            _ => match s.strip_prefix("set:") {
+                Some(build) => Self::Set(BuildMetadata::new(build)?),
+                None => bail!("expected keep, strip or set:VALUE"),
+            },
+        })
+    }
+}
+
+/// How any bump is applied to pre-release version
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum PrereleasePolicy {
+    /// Ignore pre-release and bump version core, as if it was released
+    /// (`1.2.0-beta.1` + patch bump -> `1.2.1`)
+    #[default]
+    Core,
+    /// Increment last numeric pre-release identifier (`1.0.0-rc.2` -> `1.0.0-rc.3`),
+    /// or append `.1` if there is none
+    Increment,
+    /// Release version core (`1.0.0-rc.2` -> `1.0.0`), if it is enough for bump level,
+    /// otherwise bump version core (`1.0.1-rc.2` + minor bump -> `1.1.0`)
+    Release,
+}
+
+/// Bump of dependents, caused by bump of their dependency
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyBump {
+    /// Dependents are not bumped
+    None,
+    Patch,
+    /// Minor bump for minor and major dependency bumps, patch otherwise
+    Minor,
+    /// Same bump as dependency had
+    Match,
+}
+impl DependencyBump {
+    /// Bump of dependent, which dependency had `bump`
+    pub fn propagated(self, bump: Bump) -> Bump {
+        match self {
+            Self::None => Bump::None,
+            Self::Patch => bump.min(Bump::Patch),
+            Self::Minor => bump.min(Bump::Minor),
+            Self::Match => bump,
+        }
+    }
+}
 
 /// See [`crate::generator::Verdict`]'s `bump` field
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Default, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Bump {
     #[default]
     None,
@@ -10,21 +87,51 @@ pub enum Bump {
     Minor,
     Major,
 }
+impl FromStr for Bump {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "none" => Self::None,
+            "patch" => Self::Patch,
+            "minor" => Self::Minor,
+            "major" => Self::Major,
+            _ => bail!("unknown bump '{s}', expected none/patch/minor/major"),
+        })
+    }
+}
 impl Bump {
-    pub fn from_raw(raw: BoundedI8<0, 3>) -> Self {
-        match raw.value() {
-            0 => Self::None,
-            1 => Self::Patch,
-            2 => Self::Minor,
-            3 => Self::Major,
-            _ => unreachable!("raw is bounded"),
+    /// Parse generator output, either level number (0-3) or name
+    pub fn from_raw(raw: &Val) -> anyhow::Result<Self> {
+        match raw {
+            Val::Num(n) if *n == 0.0 => Ok(Self::None),
+            Val::Num(n) if *n == 1.0 => Ok(Self::Patch),
+            Val::Num(n) if *n == 2.0 => Ok(Self::Minor),
+            Val::Num(n) if *n == 3.0 => Ok(Self::Major),
+            Val::Num(n) => bail!("unknown bump '{n}', expected 0-3 or none/patch/minor/major"),
+            Val::Str(s) => s.parse(),
+            _ => bail!("bump should be a number or a string"),
         }
     }
-    pub fn apply(&self, ver: &Version) -> Version {
+    pub fn apply(&self, ver: &Version, policy: &BumpPolicy) -> Version {
         if self == &Self::None {
             return ver.clone();
         }
-        if ver.major == 0 {
+        let mut bumped = self.bump_version(ver, policy);
+        bumped.build = match &policy.build_metadata {
+            BuildMetadataPolicy::Keep => ver.build.clone(),
+            BuildMetadataPolicy::Strip => BuildMetadata::EMPTY,
+            BuildMetadataPolicy::Set(build) => build.clone(),
+        };
+        bumped
+    }
+    fn bump_version(&self, ver: &Version, policy: &BumpPolicy) -> Version {
+        if !ver.pre.is_empty() {
+            return self.apply_prerelease(ver, policy);
+        }
+        // Under cargo semver interpretation, for 0.x versions minor component
+        // denotes breaking changes, and patch denotes everything else
+        if ver.major == 0 && !(self == &Self::Major && policy.promote_major) {
             match self {
                 Self::Major => Version {
                     major: 0,
@@ -69,4 +176,171 @@ impl Bump {
             }
         }
     }
+    fn apply_prerelease(&self, ver: &Version, policy: &BumpPolicy) -> Version {
+        match policy.prerelease {
+            PrereleasePolicy::Core => {
+                self.bump_version(&Version::new(ver.major, ver.minor, ver.patch), policy)
+            }
+            PrereleasePolicy::Increment => {
+                let mut identifiers: Vec<String> =
+                    ver.pre.split('.').map(ToOwned::to_owned).collect();
+                match identifiers.last().map(|last| last.parse::<u64>()) {
+                    Some(Ok(n)) => *identifiers.last_mut().expect("checked") = (n + 1).to_string(),
+                    _ => identifiers.push("1".to_owned()),
+                }
+                Version {
+                    pre: Prerelease::new(&identifiers.join(".")).expect("identifiers are valid"),
+                    build: BuildMetadata::EMPTY,
+                    ..ver.clone()
+                }
+            }
+            PrereleasePolicy::Release => {
+                let core = Version::new(ver.major, ver.minor, ver.patch);
+                let bumped = self.bump_version(&core, policy);
+                // Version core is not released yet, and it may already be enough
+                // for the requested bump, i.e `1.2.0-rc.1` + minor bump = `1.2.0`
+                let enough = if bumped.major != core.major {
+                    core.minor == 0 && core.patch == 0
+                } else if bumped.minor != core.minor {
+                    core.patch == 0
+                } else {
+                    true
+                };
+                if enough {
+                    core
+                } else {
+                    bumped
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(promote_major: bool) -> BumpPolicy {
+        BumpPolicy {
+            promote_major,
+            ..BumpPolicy::default()
+        }
+    }
+
+    #[test]
+    fn default_policy_matches_cli() {
+        let policy = BumpPolicy::default();
+        assert!(!policy.promote_major);
+        assert_eq!(apply("1.0.0-rc.2", Bump::Patch, &policy), "1.0.1");
+        assert_eq!(apply("1.2.3+abc", Bump::Patch, &policy), "1.2.4");
+    }
+
+    fn apply(version: &str, bump: Bump, policy: &BumpPolicy) -> String {
+        bump.apply(&Version::parse(version).unwrap(), policy)
+            .to_string()
+    }
+
+    #[test]
+    fn zero_major() {
+        let policy = policy(false);
+        assert_eq!(apply("0.1.0", Bump::Major, &policy), "0.2.0");
+        assert_eq!(apply("0.1.0", Bump::Minor, &policy), "0.1.1");
+        assert_eq!(apply("0.1.0", Bump::Patch, &policy), "0.1.1");
+        assert_eq!(apply("0.0.3", Bump::Major, &policy), "0.1.0");
+        assert_eq!(apply("0.0.3", Bump::Minor, &policy), "0.0.4");
+        assert_eq!(apply("0.0.3", Bump::Patch, &policy), "0.0.4");
+        assert_eq!(apply("1.2.3", Bump::Major, &policy), "2.0.0");
+        assert_eq!(apply("1.2.3", Bump::Minor, &policy), "1.3.0");
+        assert_eq!(apply("1.2.3", Bump::Patch, &policy), "1.2.4");
+        assert_eq!(apply("1.2.3", Bump::None, &policy), "1.2.3");
+    }
+
+    #[test]
+    fn promote_major() {
+        let policy = policy(true);
+        assert_eq!(apply("0.1.0", Bump::Major, &policy), "1.0.0");
+        assert_eq!(apply("0.1.0", Bump::Minor, &policy), "0.1.1");
+        assert_eq!(apply("0.1.0", Bump::Patch, &policy), "0.1.1");
+        assert_eq!(apply("0.0.3", Bump::Major, &policy), "1.0.0");
+        assert_eq!(apply("0.0.3", Bump::Minor, &policy), "0.0.4");
+        assert_eq!(apply("0.0.3", Bump::Patch, &policy), "0.0.4");
+        assert_eq!(apply("1.2.3", Bump::Major, &policy), "2.0.0");
+        assert_eq!(apply("1.2.3", Bump::Minor, &policy), "1.3.0");
+        assert_eq!(apply("1.2.3", Bump::Patch, &policy), "1.2.4");
+    }
+
+    fn prerelease_policy(prerelease: PrereleasePolicy) -> BumpPolicy {
+        BumpPolicy {
+            prerelease,
+            ..policy(false)
+        }
+    }
+
+    #[test]
+    fn prerelease_core() {
+        let policy = prerelease_policy(PrereleasePolicy::Core);
+        assert_eq!(apply("1.0.0-rc.2", Bump::Patch, &policy), "1.0.1");
+        assert_eq!(apply("1.0.0-rc.2", Bump::Minor, &policy), "1.1.0");
+        assert_eq!(apply("1.0.0-rc.2", Bump::Major, &policy), "2.0.0");
+        assert_eq!(apply("0.3.0-alpha.1", Bump::Major, &policy), "0.4.0");
+    }
+
+    #[test]
+    fn prerelease_increment() {
+        let policy = prerelease_policy(PrereleasePolicy::Increment);
+        assert_eq!(apply("1.0.0-rc.2", Bump::Patch, &policy), "1.0.0-rc.3");
+        assert_eq!(apply("1.0.0-rc.2", Bump::Major, &policy), "1.0.0-rc.3");
+        assert_eq!(apply("1.0.0-rc", Bump::Minor, &policy), "1.0.0-rc.1");
+        assert_eq!(apply("1.0.0-rc.2", Bump::None, &policy), "1.0.0-rc.2");
+    }
+
+    #[test]
+    fn prerelease_release() {
+        let policy = prerelease_policy(PrereleasePolicy::Release);
+        assert_eq!(apply("1.0.0-rc.2", Bump::Patch, &policy), "1.0.0");
+        assert_eq!(apply("1.0.0-rc.2", Bump::Minor, &policy), "1.0.0");
+        assert_eq!(apply("1.0.0-rc.2", Bump::Major, &policy), "1.0.0");
+        assert_eq!(apply("1.0.1-rc.2", Bump::Patch, &policy), "1.0.1");
+        assert_eq!(apply("1.0.1-rc.2", Bump::Minor, &policy), "1.1.0");
+        assert_eq!(apply("1.2.0-rc.2", Bump::Major, &policy), "2.0.0");
+    }
+
+    #[test]
+    fn build_metadata() {
+        let policy = |build_metadata: &str| BumpPolicy {
+            build_metadata: build_metadata.parse().unwrap(),
+            ..policy(false)
+        };
+        assert_eq!(
+            apply("1.2.3+abc", Bump::Patch, &policy("keep")),
+            "1.2.4+abc"
+        );
+        assert_eq!(apply("1.2.3+abc", Bump::Patch, &policy("strip")), "1.2.4");
+        assert_eq!(
+            apply("1.2.3+abc", Bump::Patch, &policy("set:build.5")),
+            "1.2.4+build.5"
+        );
+        assert_eq!(apply("1.2.3", Bump::Minor, &policy("set:ci")), "1.3.0+ci");
+        assert!("set:not valid".parse::<BuildMetadataPolicy>().is_err());
+        assert!("drop".parse::<BuildMetadataPolicy>().is_err());
+
+        let bumped = Bump::Patch.apply(
+            &Version::parse("1.0.0-rc.1+abc").unwrap(),
+            &BumpPolicy {
+                prerelease: PrereleasePolicy::Increment,
+                ..policy("keep")
+            },
+        );
+        assert_eq!(Version::parse(&bumped.to_string()).unwrap(), bumped);
+        assert_eq!(bumped.build.as_str(), "abc");
+    }
+
+    #[test]
+    fn propagated_bump_of_prerelease() {
+        let bump = DependencyBump::Patch.propagated(Bump::Minor);
+        let apply = |policy| apply("1.2.0-beta.1", bump, &prerelease_policy(policy));
+        assert_eq!(apply(PrereleasePolicy::Core), "1.2.1");
+        assert_eq!(apply(PrereleasePolicy::Increment), "1.2.0-beta.2");
+        assert_eq!(apply(PrereleasePolicy::Release), "1.2.0");
+    }
 }
@@ -0,0 +1,69 @@
+//! On-disk cache of collected commits, speeds up generator development loop
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+};
+
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{atomic, generator::Commit};
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    key: String,
+    /// By package id
+    commits: HashMap<String, Vec<Commit>>,
+    /// Walk was stopped by `--limit`
+    truncated: bool,
+}
+
+/// Cache key from everything commit collection depends on
+pub fn key(parts: impl Hash) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    parts.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Cached commits and truncation flag, if cache exists and was created with the same `key`
+pub fn load(path: &Utf8Path, key: &str) -> Option<(HashMap<String, Vec<Commit>>, bool)> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return None,
+    };
+    let cache: CacheFile = match serde_json::from_str(&text) {
+        Ok(cache) => cache,
+        Err(e) => {
+            warn!("ignoring invalid cache {path}: {e}");
+            return None;
+        }
+    };
+    if cache.key != key {
+        info!("cache {path} is outdated");
+        return None;
+    }
+    info!("using cached commits from {path}");
+    Some((cache.commits, cache.truncated))
+}
+
+pub fn store(
+    path: &Utf8Path,
+    key: &str,
+    commits: HashMap<String, Vec<Commit>>,
+    truncated: bool,
+) -> Result<()> {
+    let cache = CacheFile {
+        key: key.to_owned(),
+        commits,
+        truncated,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    atomic::write(path, &serde_json::to_string(&cache)?)
+        .with_context(|| format!("failed to write cache {path}"))
+}
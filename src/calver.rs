@@ -0,0 +1,100 @@
+//! Calendar versioning, i.e `2024.6.0`
+
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use chrono::{Datelike, NaiveDate};
+use semver::{BuildMetadata, Prerelease, Version};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DatePart {
+    /// Full year, `2024`
+    Year,
+    /// Year since 2000, `24`
+    ShortYear,
+    /// Month without leading zero, `6`
+    Month,
+}
+impl DatePart {
+    fn value(self, date: NaiveDate) -> u64 {
+        match self {
+            Self::Year => date.year() as u64,
+            Self::ShortYear => (date.year() % 100) as u64,
+            Self::Month => date.month() as u64,
+        }
+    }
+}
+impl FromStr for DatePart {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "YYYY" => Self::Year,
+            "YY" => Self::ShortYear,
+            "MM" => Self::Month,
+            _ => bail!("unknown calver component {s:?}, expected YYYY, YY or MM"),
+        })
+    }
+}
+
+/// `<major>.<minor>.PATCH` format, where major and minor are date parts,
+/// and patch is a counter, which is reset when date parts change
+#[derive(Clone, Debug)]
+pub struct CalVer {
+    major: DatePart,
+    minor: DatePart,
+}
+impl FromStr for CalVer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<_> = s.split('.').collect();
+        let (major, minor) = match parts.as_slice() {
+            [major, minor, "PATCH"] => (major.parse()?, minor.parse()?),
+            _ => bail!("calver format should look like YYYY.MM.PATCH, got {s:?}"),
+        };
+        Ok(Self { major, minor })
+    }
+}
+impl CalVer {
+    /// Version released at `date` after `current` one
+    pub fn next(&self, current: &Version, date: NaiveDate) -> Version {
+        let major = self.major.value(date);
+        let minor = self.minor.value(date);
+        let patch = if current.major == major && current.minor == minor {
+            current.patch + 1
+        } else {
+            0
+        };
+        Version {
+            major,
+            minor,
+            patch,
+            pre: Prerelease::EMPTY,
+            build: BuildMetadata::EMPTY,
+        }
+    }
+}
+
+/// How version of changed package is computed
+#[derive(Clone, Debug)]
+pub enum Versioning {
+    /// Bump level is applied to current version
+    Semver,
+    /// Version is derived from release date, bump level only marks that
+    /// release is required
+    CalVer(CalVer),
+}
+impl FromStr for Versioning {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "semver" {
+            return Ok(Self::Semver);
+        }
+        match s.strip_prefix("calver:") {
+            Some(format) => Ok(Self::CalVer(format.parse()?)),
+            None => bail!("expected semver or calver:FORMAT"),
+        }
+    }
+}
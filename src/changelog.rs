@@ -0,0 +1,369 @@
+//! Changelog text manipulation
+
+use clap::ValueEnum;
+
+use crate::generator::ChangelogEntry;
+
+/// Shift markdown headings of generator output one level down, so they are
+/// nested under package/release heading.
+///
+/// Lines inside of fenced code blocks (i.e `#[derive(...)]`) are kept as-is
+pub fn demote_headings(changelog: &str) -> String {
+    let mut out = String::new();
+    let mut fence = None;
+    for line in changelog.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") {
+            fence = Some("```");
+        } else if trimmed.starts_with("~~~") {
+            fence = Some("~~~");
+        } else if is_heading(line) {
+            out.push('#');
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn is_heading(line: &str) -> bool {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&level) && matches!(line[level..].chars().next(), None | Some(' ' | '\t'))
+}
+
+/// Where new entry is inserted
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Position {
+    /// Right after marker line, which is added to the top if missing
+    Top,
+    /// At the end, before trailing link reference definitions, marker is not used
+    Bottom,
+    /// Right before marker line, which is added to the end if missing
+    BeforeMarker,
+}
+impl Position {
+    /// Part of changelog, which holds release entries
+    pub fn releases<'t>(self, text: &'t str, marker: &str) -> &'t str {
+        match (self, text.find(marker)) {
+            (Self::Top, Some(offset)) => &text[offset + marker.len()..],
+            (Self::BeforeMarker, Some(offset)) => &text[..offset],
+            _ => text,
+        }
+    }
+}
+
+/// Join `before` and `entry` with one blank line, and `entry` with `after` too
+fn join(before: &str, entry: &str, after: &str) -> String {
+    let mut out = before.trim_end().to_owned();
+    if !out.is_empty() {
+        out.push_str("\n\n");
+    }
+    out.push_str(entry.trim_matches('\n'));
+    out.push_str("\n\n");
+    out.push_str(after.trim_start_matches('\n'));
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+/// Insert `entry` at `position` relative to `marker` line.
+///
+/// Only join points are normalized: entry is separated from the surrounding content
+/// by exactly one blank line, and file ends with exactly one newline
+pub fn insert_entry(old: &str, marker: &str, entry: &str, position: Position) -> String {
+    match position {
+        Position::Top => {}
+        Position::Bottom => {
+            let (content, links) = split_links(old);
+            return join(&content, entry, &links);
+        }
+        Position::BeforeMarker => {
+            return match old.find(marker) {
+                Some(offset) => join(&old[..offset], entry, &old[offset..]),
+                None => join(old, entry, marker),
+            }
+        }
+    }
+    let mut out = String::new();
+    let next = if let Some(offset) = old.find(marker) {
+        out.push_str(&old[..offset + marker.len()]);
+        &old[offset + marker.len()..]
+    } else {
+        out.push_str(marker);
+        old
+    };
+    out.push_str(entry.trim_end_matches('\n'));
+    out.push_str("\n\n");
+    out.push_str(next.trim_start_matches('\n'));
+
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+/// Whether `label` is among release `labels` (in order of appearance in changelog,
+/// where entries are inserted at `position`), and is the latest release
+pub fn is_latest(labels: &[&str], label: &str, position: Position) -> Option<bool> {
+    let pos = labels.iter().position(|found| *found == label)?;
+    Some(match position {
+        Position::Top => pos == 0,
+        Position::Bottom | Position::BeforeMarker => pos == labels.len() - 1,
+    })
+}
+
+/// Find entry with `label` (its heading starts with `## [<label>]`) among
+/// release entries, see [`is_latest`]
+pub fn find_release(old: &str, marker: &str, label: &str, position: Position) -> Option<bool> {
+    let labels: Vec<_> = position
+        .releases(old, marker)
+        .lines()
+        .filter_map(|line| Some(line.strip_prefix("## [")?.split_once(']')?.0))
+        .filter(|found| !found.eq_ignore_ascii_case("Unreleased"))
+        .collect();
+    is_latest(&labels, label, position)
+}
+
+/// Split text into content and trailing block of link reference definitions
+fn split_links(text: &str) -> (String, String) {
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    let block_len = lines
+        .iter()
+        .rev()
+        .take_while(|line| line.is_empty() || link_label(line).is_some())
+        .count();
+    let (content, block) = lines.split_at(lines.len() - block_len);
+    (content.join("\n"), block.join("\n"))
+}
+
+/// Label from `[label]: url` link reference definition line
+fn link_label(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('[')?;
+    let (label, url) = rest.split_once("]:")?;
+    if url.trim().is_empty() {
+        return None;
+    }
+    Some(label)
+}
+
+/// URL of `label` link reference definition
+pub fn find_link<'t>(text: &'t str, label: &str) -> Option<&'t str> {
+    text.lines().find_map(|line| {
+        let found = link_label(line)?;
+        if !found.eq_ignore_ascii_case(label) {
+            return None;
+        }
+        Some(line.split_once("]:")?.1.trim())
+    })
+}
+
+/// Replace or add link reference definitions in trailing block of `text`,
+/// new definitions are placed on top of the block
+pub fn update_links(text: &str, links: &[(String, String)]) -> String {
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    let block_len = lines
+        .iter()
+        .rev()
+        .take_while(|line| line.is_empty() || link_label(line).is_some())
+        .count();
+    let (content, block) = lines.split_at(lines.len() - block_len);
+
+    let mut out = content.join("\n");
+    out.truncate(out.trim_end().len());
+    out.push_str("\n\n");
+    for (label, url) in links {
+        out.push_str(&format!("[{label}]: {url}\n"));
+    }
+    for line in block {
+        match link_label(line) {
+            Some(label) if !links.iter().any(|(l, _)| l.eq_ignore_ascii_case(label)) => {
+                out.push_str(line);
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Render structured entries as markdown, with `## <category>` section for
+/// every category, in order of first appearance
+pub fn render_entries(entries: &[ChangelogEntry]) -> String {
+    let mut categories: Vec<&str> = vec![];
+    for entry in entries {
+        if !categories.contains(&entry.category.as_str()) {
+            categories.push(&entry.category);
+        }
+    }
+    let mut out = String::new();
+    for category in categories {
+        out.push_str(&format!("## {category}\n\n"));
+        for entry in entries.iter().filter(|e| e.category == category) {
+            let mut lines = entry.description.trim().lines();
+            out.push_str("- ");
+            if let Some(scope) = &entry.scope {
+                out.push_str(&format!("**{scope}**: "));
+            }
+            out.push_str(lines.next().unwrap_or_default());
+            if let Some(commit) = &entry.commit {
+                out.push_str(&format!(" {commit}"));
+            }
+            out.push('\n');
+            for line in lines {
+                if !line.is_empty() {
+                    out.push_str("  ");
+                }
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demote_headings_outside_of_code_blocks() {
+        let changelog = "## Fixes\n\n\
+            - Attributes are parsed:\n\n  \
+            ```rust\n  \
+            #![no_std]\n  \
+            #[derive(Debug)]\n  \
+            # hidden doc line\n  \
+            ```\n\n\
+            ### Details\n\n\
+            ~~~\n\
+            ## not a heading\n\
+            ~~~\n\
+            #hashtag\n";
+        assert_eq!(
+            demote_headings(changelog),
+            "### Fixes\n\n\
+            - Attributes are parsed:\n\n  \
+            ```rust\n  \
+            #![no_std]\n  \
+            #[derive(Debug)]\n  \
+            # hidden doc line\n  \
+            ```\n\n\
+            #### Details\n\n\
+            ~~~\n\
+            ## not a heading\n\
+            ~~~\n\
+            #hashtag\n"
+        );
+    }
+
+    const MARKER: &str = "<!-- bureaucrate goes here -->\n";
+    const ENTRY: &str = "## [v1.1.0] 2024-06-01\n\n- New\n";
+
+    #[test]
+    fn insert_at_top() {
+        let old =
+            "# Changelog\n\n<!-- bureaucrate goes here -->\n\n## [v1.0.0] 2024-01-01\n\n- Old";
+        assert_eq!(
+            insert_entry(old, MARKER, ENTRY, Position::Top),
+            "# Changelog\n\n<!-- bureaucrate goes here -->\n\
+            ## [v1.1.0] 2024-06-01\n\n- New\n\n\
+            ## [v1.0.0] 2024-01-01\n\n- Old\n"
+        );
+        // Missing marker is added
+        let old = "## [v1.0.0] 2024-01-01\n\n- Old\n\n\n";
+        assert_eq!(
+            insert_entry(old, MARKER, ENTRY, Position::Top),
+            "<!-- bureaucrate goes here -->\n\
+            ## [v1.1.0] 2024-06-01\n\n- New\n\n\
+            ## [v1.0.0] 2024-01-01\n\n- Old\n"
+        );
+        assert_eq!(
+            insert_entry("", MARKER, ENTRY, Position::Top),
+            "<!-- bureaucrate goes here -->\n## [v1.1.0] 2024-06-01\n\n- New\n"
+        );
+    }
+
+    #[test]
+    fn insert_at_bottom() {
+        let old = "# Changelog\n\n## [v1.0.0] 2024-01-01\n\n- Old\n\n\
+            [v1.0.0]: https://example.com/v1.0.0\n";
+        assert_eq!(
+            insert_entry(old, MARKER, ENTRY, Position::Bottom),
+            "# Changelog\n\n## [v1.0.0] 2024-01-01\n\n- Old\n\n\
+            ## [v1.1.0] 2024-06-01\n\n- New\n\n\
+            [v1.0.0]: https://example.com/v1.0.0\n"
+        );
+        let old = "## [v1.0.0] 2024-01-01\n\n- Old";
+        assert_eq!(
+            insert_entry(old, MARKER, ENTRY, Position::Bottom),
+            "## [v1.0.0] 2024-01-01\n\n- Old\n\n## [v1.1.0] 2024-06-01\n\n- New\n"
+        );
+    }
+
+    #[test]
+    fn insert_before_marker() {
+        let old = "# Changelog\n\n## [v1.0.0] 2024-01-01\n\n- Old\n\
+            <!-- bureaucrate goes here -->\n\n\
+            [v1.0.0]: https://example.com/v1.0.0\n";
+        assert_eq!(
+            insert_entry(old, MARKER, ENTRY, Position::BeforeMarker),
+            "# Changelog\n\n## [v1.0.0] 2024-01-01\n\n- Old\n\n\
+            ## [v1.1.0] 2024-06-01\n\n- New\n\n\
+            <!-- bureaucrate goes here -->\n\n\
+            [v1.0.0]: https://example.com/v1.0.0\n"
+        );
+        // Missing marker is added to the end
+        let old = "## [v1.0.0] 2024-01-01\n\n- Old";
+        assert_eq!(
+            insert_entry(old, MARKER, ENTRY, Position::BeforeMarker),
+            "## [v1.0.0] 2024-01-01\n\n- Old\n\n\
+            ## [v1.1.0] 2024-06-01\n\n- New\n\n\
+            <!-- bureaucrate goes here -->\n"
+        );
+    }
+
+    #[test]
+    fn trailing_links() {
+        let (content, links) =
+            split_links("- Old\n\n[v1.0.0]: https://example.com\n[v0.1.0]: x\n\n");
+        assert_eq!(content, "- Old");
+        assert_eq!(links, "\n[v1.0.0]: https://example.com\n[v0.1.0]: x");
+        // Label without URL is not a definition
+        let (content, links) = split_links("- Old\n[v1.0.0]:\n");
+        assert_eq!(content, "- Old\n[v1.0.0]:");
+        assert_eq!(links, "");
+    }
+
+    #[test]
+    fn latest_release() {
+        let newest_first = ["v1.1.0", "v1.0.0"];
+        assert_eq!(
+            is_latest(&newest_first, "v1.1.0", Position::Top),
+            Some(true)
+        );
+        assert_eq!(
+            is_latest(&newest_first, "v1.0.0", Position::Top),
+            Some(false)
+        );
+        assert_eq!(is_latest(&newest_first, "v2.0.0", Position::Top), None);
+        let oldest_first = ["v1.0.0", "v1.1.0"];
+        for position in [Position::Bottom, Position::BeforeMarker] {
+            assert_eq!(is_latest(&oldest_first, "v1.1.0", position), Some(true));
+            assert_eq!(is_latest(&oldest_first, "v1.0.0", position), Some(false));
+        }
+        assert_eq!(is_latest(&[], "v1.0.0", Position::Bottom), None);
+
+        // Releases before marker and unreleased section are ignored
+        let old = "## [v0.1.0] example\n<!-- bureaucrate goes here -->\n\
+            ## [Unreleased]\n\n## [v1.1.0] 2024-06-01\n\n## [v1.0.0] 2024-01-01\n";
+        assert_eq!(
+            find_release(old, MARKER, "v1.1.0", Position::Top),
+            Some(true)
+        );
+        assert_eq!(find_release(old, MARKER, "v0.1.0", Position::Top), None);
+    }
+}
@@ -0,0 +1,69 @@
+//! `bureaucrate.toml` and per-package configuration
+//!
+//! Global values are applied with precedence: command line > environment > file > built-in defaults
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+
+use crate::bump::DependencyBump;
+
+pub const FILE_NAME: &str = "bureaucrate.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Relative to config file directory
+    pub generator: Option<PathBuf>,
+    pub insertion_marker: Option<String>,
+    pub changelog_name: Option<Utf8PathBuf>,
+    pub dependency_bump: Option<DependencyBump>,
+    pub date_format: Option<String>,
+    /// `{name}` is replaced with package name
+    pub tag_prefix: Option<String>,
+}
+
+/// Find config file in `start` directory or any of its parents
+pub fn find(start: &Path) -> Result<Option<Config>> {
+    let start = start.canonicalize()?;
+    for dir in start.ancestors() {
+        let path = dir.join(FILE_NAME);
+        if !path.is_file() {
+            continue;
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("invalid {}", path.display()))?;
+        config.generator = config.generator.map(|generator| dir.join(generator));
+        return Ok(Some(config));
+    }
+    Ok(None)
+}
+
+/// `[package.metadata.bureaucrate]` table, overrides global options for package
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PackageConfig {
+    /// Changelog path, relative to package directory
+    pub changelog: Option<Utf8PathBuf>,
+    /// When false, package is handled as internal one: it doesn't get
+    /// changelog entries and version bumps
+    pub publish_bumps: Option<bool>,
+    /// Prefix of release tags, overrides global `tag-prefix`
+    pub tag_prefix: Option<String>,
+}
+impl PackageConfig {
+    pub fn from_metadata(name: &str, metadata: &serde_json::Value) -> Result<Self> {
+        match metadata.get("bureaucrate") {
+            Some(table) => Self::deserialize(table)
+                .with_context(|| format!("invalid package.metadata.bureaucrate of {name}")),
+            None => Ok(Self::default()),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Built-in commit processor for `--preset conventional`, based on example
+//! `generator.jsonnet`. Unlike it, `docs` type is accepted, and header of `!`
+//! commit without `BREAKING CHANGE: ` footer is listed as breaking change
+
+use std::fmt::Write as _;
+
+use anyhow::{anyhow, Result};
+
+use crate::{bump::Bump, generator::Commit};
+
+const VALID_TYPES: [&str; 10] = [
+    "feat", "fix", "refactor", "build", "ci", "doc", "docs", "test", "style", "chore",
+];
+const VALID_TRAILERS: [&str; 4] = ["Cc", "Signed-off-by", "Reviewed-by", "Co-authored-by"];
+
+struct Header<'c> {
+    kind: &'c str,
+    bang: bool,
+    message: &'c str,
+}
+
+struct ParsedCommit<'c> {
+    id: &'c str,
+    header: Header<'c>,
+    description: Option<String>,
+    breaking: Option<String>,
+    product: Option<String>,
+}
+
+fn capitalize(line: &str) -> String {
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Remove empty lines at beginning and on end of slice
+fn trim_empty_lines<'a, 'l>(lines: &'a [&'l str]) -> &'a [&'l str] {
+    let start = lines.iter().take_while(|l| l.is_empty()).count();
+    let end = lines.len()
+        - lines[start..]
+            .iter()
+            .rev()
+            .take_while(|l| l.is_empty())
+            .count();
+    &lines[start..end]
+}
+
+fn is_trailer_line(line: &str) -> bool {
+    line.is_empty()
+        || VALID_TRAILERS
+            .iter()
+            .any(|trailer| line.starts_with(&format!("{trailer}: ")))
+}
+
+/// Split BREAKING CHANGES or other category from line list, category spans to the end of lines
+fn split_category<'l>(lines: &[&'l str], category: &str) -> (Vec<&'l str>, Option<String>) {
+    match lines.iter().position(|l| l.starts_with(category)) {
+        Some(pos) => (
+            trim_empty_lines(&lines[..pos]).to_vec(),
+            Some(lines[pos..].join("\n")[category.len()..].to_owned()),
+        ),
+        None => (trim_empty_lines(lines).to_vec(), None),
+    }
+}
+
+fn parse_header(header: &str) -> Option<Header<'_>> {
+    // Type is followed by scope, bang or colon, so `docs` isn't mistaken for `doc`
+    let kind = VALID_TYPES.iter().find(|kind| {
+        let next = header
+            .strip_prefix(*kind)
+            .and_then(|rest| rest.chars().next());
+        matches!(next, Some('(' | '!' | ':'))
+    })?;
+    let mut rest = &header[kind.len()..];
+    if let Some(scoped) = rest.strip_prefix('(') {
+        let end = scoped.find(')')?;
+        let scope = &scoped[..end];
+        if !scope
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || matches!(c, '-' | ' ' | '_'))
+        {
+            return None;
+        }
+        rest = &scoped[end + 1..];
+    }
+    let (bang, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let message = rest.strip_prefix(": ")?;
+    Some(Header {
+        kind: &header[..kind.len()],
+        bang,
+        message,
+    })
+}
+
+fn parse_commit(commit: &Commit) -> Result<ParsedCommit<'_>> {
+    let lines: Vec<&str> = commit.message.split('\n').collect();
+    let lines = trim_empty_lines(&lines);
+    let header = lines.first().copied().unwrap_or_default();
+    let body = trim_empty_lines(lines.get(1..).unwrap_or_default());
+    let trailers = body.iter().rev().take_while(|l| is_trailer_line(l)).count();
+    let description = trim_empty_lines(&body[..body.len() - trailers]);
+
+    let (rest, breaking) = split_category(description, "BREAKING CHANGE: ");
+    let (rest, product) = split_category(&rest, "PRODUCT: ");
+    let description = rest.join("\n");
+
+    let header = parse_header(header).ok_or_else(|| {
+        anyhow!(
+            "commit title parse error: {} is not a conventional commit\ntried to parse: {header}",
+            commit.id
+        )
+    })?;
+    // `!` without footer still marks breaking change
+    let breaking = match breaking {
+        None if header.bang => Some(header.message.to_owned()),
+        breaking => breaking,
+    };
+    Ok(ParsedCommit {
+        id: &commit.id,
+        header,
+        description: if description.is_empty() {
+            None
+        } else {
+            Some(description)
+        },
+        breaking,
+        product,
+    })
+}
+
+fn section(out: &mut String, title: &str, entries: &[String]) {
+    if entries.is_empty() {
+        return;
+    }
+    write!(out, "## {title}\n\n{}\n\n", entries.join("\n\n")).expect("string write");
+}
+
+/// Returns changelog and required bump
+pub fn commit_handler(commits: &[Commit]) -> Result<(String, Bump)> {
+    let parsed = commits
+        .iter()
+        .map(parse_commit)
+        .collect::<Result<Vec<_>>>()?;
+
+    let product: Vec<_> = parsed
+        .iter()
+        .filter_map(|c| c.product.as_ref())
+        .map(|p| format!("- {p}"))
+        .collect();
+    let breaking: Vec<_> = parsed
+        .iter()
+        .filter_map(|c| c.breaking.as_ref())
+        .map(|p| format!("- {p}"))
+        .collect();
+    let mut features = vec![];
+    let mut other_features = vec![];
+    let mut fixes = vec![];
+    let mut other = vec![];
+    for c in &parsed {
+        let message = capitalize(c.header.message);
+        let description = c
+            .description
+            .as_ref()
+            .map(|d| format!("\n\n{d}"))
+            .unwrap_or_default();
+        match (c.header.kind, &c.description) {
+            ("feat", Some(d)) => features.push(format!("### {message} {}\n\n{d}", c.id)),
+            ("feat", None) => other_features.push(format!("- {message} {}", c.id)),
+            ("fix", _) => fixes.push(format!("- {message} {}{description}", c.id)),
+            (kind, _) => other.push(format!("- {kind}: {message} {}{description}", c.id)),
+        }
+    }
+
+    let mut changelog = String::new();
+    section(&mut changelog, "Product changes", &product);
+    section(&mut changelog, "Breaking changes", &breaking);
+    section(&mut changelog, "Added features", &features);
+    section(
+        &mut changelog,
+        if features.is_empty() {
+            "Added features"
+        } else {
+            "Other features"
+        },
+        &other_features,
+    );
+    section(&mut changelog, "Bugfixes", &fixes);
+    section(&mut changelog, "Other changes", &other);
+
+    let bump = if !breaking.is_empty() {
+        Bump::Major
+    } else if parsed.iter().any(|c| c.header.kind == "feat") {
+        Bump::Minor
+    } else if !parsed.is_empty() {
+        Bump::Patch
+    } else {
+        Bump::None
+    };
+    Ok((changelog, bump))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(id: &str, message: &str) -> Commit {
+        Commit {
+            id: id.to_owned(),
+            message: message.to_owned(),
+            author_name: "Author".to_owned(),
+            author_email: "author@example.com".to_owned(),
+            committer_name: "Author".to_owned(),
+            committer_email: "author@example.com".to_owned(),
+            trailers: Default::default(),
+            co_authors: vec![],
+            author_date: "2024-06-01T00:00:00+00:00".to_owned(),
+            committer_date: "2024-06-01T00:00:00+00:00".to_owned(),
+            files: vec![],
+            insertions: 0,
+            deletions: 0,
+            files_changed: 0,
+            parent_ids: vec![],
+            is_merge: false,
+            url: None,
+            touched_packages: vec![],
+            submodules: vec![],
+        }
+    }
+
+    fn handle(messages: &[&str]) -> Result<(String, Bump)> {
+        let commits: Vec<_> = messages
+            .iter()
+            .enumerate()
+            .map(|(idx, message)| commit(&format!("c{idx}"), message))
+            .collect();
+        commit_handler(&commits)
+    }
+
+    #[test]
+    fn header_types() {
+        let header = parse_header("docs: describe presets").unwrap();
+        assert_eq!(header.kind, "docs");
+        assert_eq!(header.message, "describe presets");
+        assert_eq!(parse_header("doc: describe presets").unwrap().kind, "doc");
+        assert!(parse_header("feature: add presets").is_none());
+        assert!(parse_header("fixup: typo").is_none());
+        assert!(parse_header("feat:no space").is_none());
+    }
+
+    #[test]
+    fn scoped_header() {
+        let header = parse_header("fix(plan view): escape names").unwrap();
+        assert_eq!(header.kind, "fix");
+        assert!(!header.bang);
+        assert_eq!(header.message, "escape names");
+        let header = parse_header("feat(cli)!: drop --root").unwrap();
+        assert!(header.bang);
+        assert!(parse_header("fix(v2): escape names").is_none());
+        assert!(parse_header("fix(cli: escape names").is_none());
+    }
+
+    #[test]
+    fn bang_without_footer() {
+        let (changelog, bump) = handle(&["feat!: drop --root"]).unwrap();
+        assert_eq!(bump, Bump::Major);
+        assert_eq!(
+            changelog,
+            "## Breaking changes\n\n- drop --root\n\n## Added features\n\n- Drop --root c0\n\n"
+        );
+    }
+
+    #[test]
+    fn breaking_change_footer_before_trailers() {
+        let (changelog, bump) = handle(&["fix: parse tags\n\n\
+            Tags are sorted by version.\n\n\
+            BREAKING CHANGE: unsorted tags are rejected\n\n\
+            Signed-off-by: Author <author@example.com>\n"])
+        .unwrap();
+        assert_eq!(bump, Bump::Major);
+        assert_eq!(
+            changelog,
+            "## Breaking changes\n\n- unsorted tags are rejected\n\n\
+            ## Bugfixes\n\n- Parse tags c0\n\nTags are sorted by version.\n\n"
+        );
+    }
+
+    #[test]
+    fn feature_sections() {
+        let (changelog, bump) = handle(&[
+            "feat: add presets\n\nPresets replace --generator.",
+            "feat: add --cache",
+            "chore: update deps",
+        ])
+        .unwrap();
+        assert_eq!(bump, Bump::Minor);
+        assert_eq!(
+            changelog,
+            "## Added features\n\n### Add presets c0\n\nPresets replace --generator.\n\n\
+            ## Other features\n\n- Add --cache c1\n\n\
+            ## Other changes\n\n- chore: Update deps c2\n\n"
+        );
+
+        let (changelog, _) = handle(&["feat: add --cache"]).unwrap();
+        assert_eq!(changelog, "## Added features\n\n- Add --cache c0\n\n");
+    }
+
+    #[test]
+    fn non_conventional_header() {
+        let error = handle(&["fix: parse tags", "Update README"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "commit title parse error: c1 is not a conventional commit\ntried to parse: Update README"
+        );
+        assert_eq!(handle(&[]).unwrap(), (String::new(), Bump::None));
+    }
+}
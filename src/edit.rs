@@ -0,0 +1,105 @@
+//! File changes made by `--execute`, computed before anything is written
+
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use git2::Patch;
+use tracing::info;
+
+use crate::atomic;
+
+pub struct Edit {
+    pub path: Utf8PathBuf,
+    /// `None` if file doesn't exist yet
+    pub old: Option<String>,
+    pub new: String,
+}
+impl Edit {
+    /// Unified diff in `git apply` format, paths are relative to `root`
+    pub fn diff(&self, root: &Utf8Path) -> Result<String> {
+        let path = self.path.strip_prefix(root).unwrap_or(&self.path);
+        let old = self.old.as_deref().unwrap_or_default();
+        let mut patch = Patch::from_buffers(
+            old.as_bytes(),
+            Some(path.as_std_path()),
+            self.new.as_bytes(),
+            Some(path.as_std_path()),
+            None,
+        )?;
+        let buf = patch.to_buf()?;
+        let text = buf
+            .as_str()
+            .ok_or_else(|| anyhow!("diff of {path} is not utf-8"))?;
+        let hunks = match text.find("\n@@") {
+            Some(offset) => &text[offset + 1..],
+            None => return Ok(String::new()),
+        };
+        // Header is written manually, as libgit2 doesn't know that file is new
+        let mut out = format!("diff --git a/{path} b/{path}\n");
+        match self.old {
+            Some(_) => out.push_str(&format!("--- a/{path}\n")),
+            None => out.push_str("new file mode 100644\n--- /dev/null\n"),
+        }
+        out.push_str(&format!("+++ b/{path}\n{hunks}"));
+        Ok(out)
+    }
+
+    pub fn write(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic::write(&self.path, &self.new)
+    }
+}
+
+/// Pending edits in order of creation, edits of the same file are merged
+#[derive(Default)]
+pub struct Edits(Vec<Edit>);
+impl Edits {
+    /// Contents of `path` with pending edits applied, if there is any
+    pub fn current(&self, path: &Utf8Path) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|edit| edit.path == path)
+            .map(|edit| edit.new.as_str())
+    }
+
+    /// Add edit, which is based on [`Self::current`] contents
+    pub fn push(&mut self, edit: Edit) {
+        let pos = match self.0.iter().position(|e| e.path == edit.path) {
+            Some(pos) => pos,
+            None => {
+                self.0.push(edit);
+                return;
+            }
+        };
+        if self.0[pos].old.as_deref() == Some(edit.new.as_str()) {
+            self.0.remove(pos);
+        } else {
+            self.0[pos].new = edit.new;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Edit> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Write every edit as `NNNN-<path>.patch` into `dir`, numbered in order of edits,
+/// so `git apply <dir>/*.patch` applies all of them
+pub fn emit_patches(edits: &Edits, root: &Utf8Path, dir: &Utf8Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {dir}"))?;
+    for (idx, edit) in edits.iter().enumerate() {
+        let path = edit.path.strip_prefix(root).unwrap_or(&edit.path);
+        let name = format!("{:04}-{}.patch", idx + 1, path.as_str().replace('/', "-"));
+        let patch = dir.join(name);
+        info!("writing {patch}");
+        atomic::write(&patch, &edit.diff(root)?)?;
+    }
+    Ok(())
+}
@@ -1,13 +1,16 @@
 //! Types used by `--generator` code
 
-use std::{marker::PhantomData, ops::Deref};
+use std::{collections::BTreeMap, marker::PhantomData, ops::Deref, path::PathBuf};
 
+use anyhow::{anyhow, Context};
 use jrsonnet_evaluator::{
-    error::{Error, Result},
-    function::native::NativeDesc,
-    typed::{BoundedI8, CheckType, ComplexValType, Typed, ValType},
-    State, Val,
+    error::{Error, LocError, Result},
+    function::{native::NativeDesc, FuncVal},
+    trace::{CompactFormat, PathResolver},
+    typed::{CheckType, ComplexValType, Typed, ValType},
+    FileImportResolver, ObjValueBuilder, State, Val,
 };
+use serde::{Deserialize, Serialize};
 
 // TODO: Move to jrsonnet_evaluator::typed
 pub struct NativeFn<T>(PhantomData<T>, T::Value)
@@ -42,7 +45,7 @@ where
 }
 
 /// Generator input is [`Vec<Commit>`]
-#[derive(jrsonnet_evaluator::typed::Typed, Debug, Clone)]
+#[derive(jrsonnet_evaluator::typed::Typed, Serialize, Deserialize, Debug, Clone)]
 pub struct Commit {
     pub id: String,
     pub message: String,
@@ -50,13 +53,120 @@ pub struct Commit {
     pub author_name: String,
     #[typed(rename = "authorEmail")]
     pub author_email: String,
+    /// Who applied the commit, i.e. merged pull request
+    #[typed(rename = "committerName")]
+    pub committer_name: String,
+    #[typed(rename = "committerEmail")]
+    pub committer_email: String,
+    /// Trailers from the last paragraph of message, i.e. `Fixes: #123`
+    pub trailers: Trailers,
+    /// Parsed from `Co-authored-by` trailers
+    #[typed(rename = "coAuthors")]
+    pub co_authors: Vec<CoAuthor>,
+    /// RFC3339 formatted, in author timezone
+    #[typed(rename = "authorDate")]
+    pub author_date: String,
+    /// RFC3339 formatted, in committer timezone
+    #[typed(rename = "committerDate")]
+    pub committer_date: String,
+    /// Paths touched by this commit in package directories (for renames
+    /// both sides are listed), relative to repository root
+    pub files: Vec<String>,
+    /// Added lines in package files, merge commits are compared to first parent
+    pub insertions: usize,
+    /// Removed lines in package files, merge commits are compared to first parent
+    pub deletions: usize,
+    /// Number of changed package files, renamed file is counted once
+    #[typed(rename = "filesChanged")]
+    pub files_changed: usize,
+    #[typed(rename = "parentIds")]
+    pub parent_ids: Vec<String>,
+    /// Commit has more than one parent
+    #[typed(rename = "isMerge")]
+    pub is_merge: bool,
+    /// Link to commit on git hosting, if remote is recognized
+    pub url: Option<String>,
+    /// Names of all workspace packages, which files were changed by commit,
+    /// sorted. Includes the package commit is passed for
+    #[typed(rename = "touchedPackages")]
+    pub touched_packages: Vec<String>,
+    /// Submodules inside of package directories, which were updated by commit
+    pub submodules: Vec<SubmoduleUpdate>,
+}
+
+/// Commit trailers by key, values of repeated keys are listed in order of appearance
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct Trailers(pub BTreeMap<String, Vec<String>>);
+
+// Exposed to jsonnet as object of arrays
+impl Typed for Trailers {
+    const TYPE: &'static ComplexValType = &ComplexValType::Simple(ValType::Obj);
+
+    fn into_untyped(typed: Self, s: State) -> Result<Val> {
+        let mut builder = ObjValueBuilder::new();
+        for (key, values) in typed.0 {
+            builder
+                .member(key.as_str().into())
+                .value(s.clone(), Vec::<String>::into_untyped(values, s.clone())?)?;
+        }
+        Ok(Val::Obj(builder.build()))
+    }
+
+    fn from_untyped(_untyped: Val, _s: State) -> Result<Self> {
+        Err(Error::RuntimeError("trailers are only passed to generator".into()).into())
+    }
+}
+
+/// Change of submodule pointer (gitlink)
+#[derive(jrsonnet_evaluator::typed::Typed, Serialize, Deserialize, Debug, Clone)]
+pub struct SubmoduleUpdate {
+    pub path: String,
+    /// Previous submodule commit, `null` if submodule was added
+    #[typed(rename = "oldId")]
+    pub old_id: Option<String>,
+    /// New submodule commit, `null` if submodule was removed
+    #[typed(rename = "newId")]
+    pub new_id: Option<String>,
+}
+
+/// Person from `Co-authored-by` trailer
+#[derive(jrsonnet_evaluator::typed::Typed, Serialize, Deserialize, Debug, Clone)]
+pub struct CoAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+/// Package, for which changelog is generated, optional second argument of `commitHandler`
+#[derive(jrsonnet_evaluator::typed::Typed, Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    #[typed(rename = "currentVersion")]
+    pub current_version: String,
+    /// HTTPS URL of repository web interface, if remote is recognized
+    #[typed(rename = "repositoryUrl")]
+    pub repository_url: Option<String>,
+}
+
+/// Single change, rendered by bureaucrate into the chosen changelog format
+#[derive(jrsonnet_evaluator::typed::Typed, Serialize, Debug, Clone)]
+pub struct ChangelogEntry {
+    /// Section title, i.e `Bugfixes`
+    pub category: String,
+    pub scope: Option<String>,
+    /// Markdown formatted, first line is used as list item, rest is indented under it
+    pub description: String,
+    /// Commit id
+    pub commit: Option<String>,
 }
 
 /// Generator output
 #[derive(jrsonnet_evaluator::typed::Typed)]
 pub struct Verdict {
-    /// Markdown formatted changelog
-    pub changelog: String,
+    /// Markdown formatted changelog, legacy alternative to `entries`.
+    /// When both are set, `changelog` is placed after rendered entries
+    pub changelog: Option<String>,
+    pub entries: Option<Vec<ChangelogEntry>>,
     /// 0 - no bump required, however we can still have changelog
     ///     useable for `ci:` or `style:` changes.
     /// 1 - patch bump is required.
@@ -64,13 +174,247 @@ pub struct Verdict {
     ///     version - then patch version will be bumped instead.
     /// 3 - major bump. If previous version of crate had zero major
     ///     version - then minor version will be bumped, real major
-    ///     bump (release) require manual intervention instead
-    // TODO: impl Typed for Bump
-    pub bump: BoundedI8<0, 3>,
+    ///     bump (release) requires `--promote-major` instead
+    ///
+    /// Names (`none`, `patch`, `minor`, `major`) are accepted too,
+    /// validated by [`crate::bump::Bump::from_raw`]
+    pub bump: Val,
+    /// Exact version to release, should be greater than current one.
+    /// When set, `bump` is only used for propagation to dependents
+    pub version: Option<String>,
+}
+
+/// Package summary, input of `planHandler`
+#[derive(jrsonnet_evaluator::typed::Typed, Debug, Clone)]
+pub struct PlannedPackage {
+    pub name: String,
+    #[typed(rename = "currentVersion")]
+    pub current_version: String,
+    #[typed(rename = "finalVersion")]
+    pub final_version: String,
+    /// `none`, `patch`, `minor` or `major`
+    pub bump: String,
+    #[typed(rename = "bumpReasons")]
+    pub bump_reasons: Vec<String>,
+    pub changelog: String,
+}
+
+/// `commitHandler` of generator, `package` argument may be omitted,
+/// as generators written before it was added only take commits
+pub enum CommitHandler {
+    Commits(NativeFn<((Vec<Commit>,), Verdict)>),
+    CommitsAndPackage(NativeFn<((Vec<Commit>, Package), Verdict)>),
 }
+impl Typed for CommitHandler {
+    const TYPE: &'static ComplexValType = &ComplexValType::Simple(ValType::Func);
+
+    fn into_untyped(_typed: Self, _s: State) -> Result<Val> {
+        Err(Error::RuntimeError("can't convert arbitrary function to native".into()).into())
+    }
 
+    fn from_untyped(untyped: Val, s: State) -> Result<Self> {
+        let commits_only =
+            matches!(&untyped, Val::Func(FuncVal::Normal(desc)) if desc.params.len() == 1);
+        Ok(if commits_only {
+            Self::Commits(NativeFn::from_untyped(untyped, s)?)
+        } else {
+            Self::CommitsAndPackage(NativeFn::from_untyped(untyped, s)?)
+        })
+    }
+}
+impl CommitHandler {
+    pub fn call(&self, s: State, commits: Vec<Commit>, package: Package) -> Result<Verdict> {
+        match self {
+            Self::Commits(handler) => handler(s, commits),
+            Self::CommitsAndPackage(handler) => handler(s, commits, package),
+        }
+    }
+}
+
+/// Object exported by generator:
+///
+/// ```jsonnet
+/// {
+///   // `package` may be omitted: commitHandler(commits)
+///   commitHandler(commits, package): { changelog: '...', bump: 1 },
+///   planHandler(packages): '...', // optional
+/// }
+/// ```
 #[derive(jrsonnet_evaluator::typed::Typed)]
 pub struct Generator {
     #[typed(rename = "commitHandler")]
-    pub commit_handler: NativeFn<((Vec<Commit>,), Verdict)>,
+    pub commit_handler: CommitHandler,
+    /// Renders markdown dry-run plan, built-in template is used if missing
+    #[typed(rename = "planHandler")]
+    pub plan_handler: Option<NativeFn<((Vec<PlannedPackage>,), String)>>,
+}
+
+/// Jsonnet state with imported generator.
+///
+/// [`State`] is not thread-safe, so every worker thread needs its own evaluator
+pub struct Evaluator {
+    pub state: State,
+    pub generator: Generator,
+    name: String,
+}
+/// Where generator code comes from
+pub enum Source {
+    File(PathBuf),
+    /// Code passed with `--generator-inline` or read from stdin
+    Inline(String),
+}
+impl Source {
+    /// Name used in error messages
+    fn name(&self) -> String {
+        match self {
+            Self::File(path) => path.display().to_string(),
+            Self::Inline(_) => INLINE_NAME.to_owned(),
+        }
+    }
+}
+
+const INLINE_NAME: &str = "<inline generator>";
+
+/// Jsonnet error with its stack trace, every frame has `file:line:column` location
+fn error(s: &State, context: &str, e: &LocError) -> anyhow::Error {
+    anyhow!("{context}:\n{}", s.stringify_err(e))
+}
+
+/// Check shape of exported value, before type errors of [`Generator`]
+/// conversion, which don't point at missing field
+fn validate(generator: &Val, s: &State, name: &str) -> anyhow::Result<()> {
+    let handler = match generator {
+        Val::Obj(obj) => obj
+            .get(s.clone(), "commitHandler".into())
+            .map_err(|e| error(s, &format!("generator {name}"), &e))?,
+        _ => None,
+    };
+    if !matches!(handler, Some(Val::Func(_))) {
+        return Err(anyhow!(
+            "generator {name} must export a 'commitHandler' function taking (commits) or (commits, package)"
+        ));
+    }
+    Ok(())
+}
+
+impl Evaluator {
+    pub fn new(
+        source: &Source,
+        library_paths: &[PathBuf],
+        ext_str: &[(String, String)],
+        ext_code: &[(String, String)],
+    ) -> anyhow::Result<Self> {
+        let s = State::default();
+        // Paths in traces are relative to working directory, as in arguments
+        s.set_trace_format(Box::new(CompactFormat {
+            resolver: PathResolver::Relative(std::env::current_dir()?),
+            padding: 4,
+        }));
+        s.set_import_resolver(Box::new(FileImportResolver {
+            library_paths: library_paths.to_vec(),
+        }));
+        s.with_stdlib();
+        for (key, value) in ext_str {
+            s.add_ext_str(key.as_str().into(), value.as_str().into());
+        }
+        for (key, code) in ext_code {
+            s.add_ext_code(key.as_str().into(), code.as_str().into())
+                .map_err(|e| error(&s, &format!("ext code {key}"), &e))?;
+        }
+
+        // Generator is imported once, jsonnet is pure, so calling the same handler
+        // for every package is fine, and imports (along with their evaluated fields) are cached
+        let name = source.name();
+        let generator = match source {
+            Source::File(path) => s.import(
+                path.canonicalize()
+                    .with_context(|| format!("failed to open generator {name}"))?,
+            ),
+            Source::Inline(code) => s.evaluate_snippet(INLINE_NAME, code.as_str()),
+        }
+        .map_err(|e| error(&s, &format!("failed to evaluate generator {name}"), &e))?;
+        validate(&generator, &s, &name)?;
+        let generator = Generator::from_untyped(generator, s.clone())
+            .map_err(|e| error(&s, &format!("generator {name} has invalid shape"), &e))?;
+        Ok(Self {
+            state: s,
+            generator,
+            name,
+        })
+    }
+
+    /// Wrap error of generator call, `context` describes what was called
+    pub fn error(&self, context: &str, e: &LocError) -> anyhow::Error {
+        error(
+            &self.state,
+            &format!("{context} of generator {}", self.name),
+            e,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::bump::Bump;
+
+    fn evaluate(code: &str) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("generator.jsonnet");
+        fs::write(&path, code).unwrap();
+        let error = Evaluator::new(&Source::File(path.clone()), &[], &[], &[])
+            .err()
+            .expect("generator is invalid");
+        let message = format!("{error:#}");
+        assert!(message.contains(&path.display().to_string()), "{message}");
+        message
+    }
+
+    #[test]
+    fn syntax_error_location() {
+        let message = evaluate("{\n  commitHandler(commits)::\n}\n");
+        assert!(message.contains("generator.jsonnet:3:"), "{message}");
+    }
+
+    #[test]
+    fn handler_without_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("generator.jsonnet");
+        let package = || Package {
+            name: "a".to_owned(),
+            current_version: "1.0.0".to_owned(),
+            repository_url: None,
+        };
+        let bump = |code: &str| {
+            fs::write(&path, code).unwrap();
+            let evaluator = Evaluator::new(&Source::File(path.clone()), &[], &[], &[]).unwrap();
+            let verdict = evaluator
+                .generator
+                .commit_handler
+                .call(evaluator.state.clone(), vec![], package())
+                .map_err(|e| evaluator.error("commitHandler", &e))
+                .unwrap();
+            Bump::from_raw(&verdict.bump).unwrap()
+        };
+        assert_eq!(bump("{ commitHandler(commits): { bump: 1 } }"), Bump::Patch);
+        assert_eq!(
+            bump("{ commitHandler(commits, package): { bump: if package.name == 'a' then 2 else 0 } }"),
+            Bump::Minor
+        );
+        assert_eq!(
+            bump("{ commitHandler(commits, package=null): { bump: if package == null then 0 else 3 } }"),
+            Bump::Major
+        );
+    }
+
+    #[test]
+    fn missing_handler() {
+        let message = evaluate("{ planHandler(packages): '' }");
+        assert!(
+            message.contains("must export a 'commitHandler' function"),
+            "{message}"
+        );
+    }
 }
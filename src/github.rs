@@ -0,0 +1,88 @@
+//! Publishing dry-run plan as GitHub pull request comment
+
+use std::env;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use tracing::info;
+
+/// Hidden marker, used to find previously posted comment
+const MARKER: &str = "<!-- bureaucrate plan -->";
+const API: &str = "https://api.github.com";
+
+#[derive(Deserialize)]
+struct Comment {
+    id: u64,
+    body: Option<String>,
+}
+
+/// Pull request number from `GITHUB_REF` (`refs/pull/<number>/merge`)
+pub fn pr_from_env() -> Option<u64> {
+    let github_ref = env::var("GITHUB_REF").ok()?;
+    github_ref
+        .strip_prefix("refs/pull/")?
+        .split('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn request(method: &str, url: &str, token: &str) -> ureq::Request {
+    ureq::request(method, url)
+        .set("Authorization", &format!("Bearer {token}"))
+        .set("Accept", "application/vnd.github+json")
+        .set("User-Agent", "bureaucrate")
+}
+
+/// Create comment with `plan` on pull request `pr`, or update existing one
+pub fn upsert_comment(pr: u64, plan: &str) -> Result<()> {
+    let token = env::var("GITHUB_TOKEN").context("GITHUB_TOKEN is not set")?;
+    let repository = env::var("GITHUB_REPOSITORY").context("GITHUB_REPOSITORY is not set")?;
+    let body = format!("{MARKER}\n{plan}");
+
+    let mut existing = None;
+    for page in 1.. {
+        let comments: Vec<Comment> = request(
+            "GET",
+            &format!("{API}/repos/{repository}/issues/{pr}/comments?per_page=100&page={page}"),
+            &token,
+        )
+        .call()
+        .map_err(|e| anyhow!("failed to list comments of #{pr}: {e}"))?
+        .into_json()?;
+        if comments.is_empty() {
+            break;
+        }
+        existing = comments
+            .into_iter()
+            .find(|c| matches!(&c.body, Some(body) if body.starts_with(MARKER)));
+        if existing.is_some() {
+            break;
+        }
+    }
+
+    let payload = serde_json::json!({ "body": body });
+    match existing {
+        Some(comment) => {
+            info!("updating plan comment {} on #{pr}", comment.id);
+            request(
+                "PATCH",
+                &format!("{API}/repos/{repository}/issues/comments/{}", comment.id),
+                &token,
+            )
+            .send_json(payload)
+            .map_err(|e| anyhow!("failed to update comment on #{pr}: {e}"))?;
+        }
+        None => {
+            info!("creating plan comment on #{pr}");
+            request(
+                "POST",
+                &format!("{API}/repos/{repository}/issues/{pr}/comments"),
+                &token,
+            )
+            .send_json(payload)
+            .map_err(|e| anyhow!("failed to create comment on #{pr}: {e}"))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,209 @@
+//! [Keep a Changelog](https://keepachangelog.com/en/1.0.0/) formatted changelog
+
+use crate::changelog::{self, Position};
+
+const UNRELEASED: &str = "## [Unreleased]";
+/// Sections in recommended order
+const SECTIONS: [&str; 6] = [
+    "Added",
+    "Changed",
+    "Deprecated",
+    "Removed",
+    "Fixed",
+    "Security",
+];
+
+/// Map generator section title to Keep a Changelog one
+fn section_name(title: &str) -> &str {
+    match title.to_lowercase().as_str() {
+        "added features" | "other features" | "features" => "Added",
+        "bugfixes" | "bug fixes" | "fixes" => "Fixed",
+        "breaking changes" | "product changes" | "other changes" | "changes" => "Changed",
+        _ => SECTIONS
+            .iter()
+            .find(|s| s.eq_ignore_ascii_case(title))
+            .copied()
+            .unwrap_or(title),
+    }
+}
+
+/// Merge `### ` sections with the same Keep a Changelog name, known sections
+/// are sorted in recommended order, unknown ones are kept after them
+fn merge_sections(body: &str) -> String {
+    let mut preamble = String::new();
+    let mut sections: Vec<(String, String)> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut fence = false;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = !fence;
+        }
+        if let Some(title) = line.strip_prefix("### ").filter(|_| !fence) {
+            let name = section_name(title.trim()).to_owned();
+            current = Some(match sections.iter().position(|(n, _)| n == &name) {
+                Some(pos) => pos,
+                None => {
+                    sections.push((name, String::new()));
+                    sections.len() - 1
+                }
+            });
+            continue;
+        }
+        let out = match current {
+            Some(idx) => &mut sections[idx].1,
+            None => &mut preamble,
+        };
+        out.push_str(line);
+        out.push('\n');
+    }
+    sections.sort_by_key(|(name, _)| {
+        SECTIONS
+            .iter()
+            .position(|s| s == name)
+            .unwrap_or(SECTIONS.len())
+    });
+
+    let mut out = String::new();
+    if !preamble.trim().is_empty() {
+        out.push_str(preamble.trim());
+        out.push_str("\n\n");
+    }
+    for (name, content) in sections {
+        let content = content.trim();
+        if content.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("### {name}\n\n{content}\n\n"));
+    }
+    out
+}
+
+/// Remove `## [Unreleased]` section, returning changelog without it and its content
+fn take_unreleased(old: &str) -> (String, String) {
+    let mut rest = String::new();
+    let mut unreleased = String::new();
+    let mut in_unreleased = false;
+    for line in old.lines() {
+        if line.to_lowercase().starts_with(&UNRELEASED.to_lowercase()) {
+            in_unreleased = true;
+            continue;
+        }
+        if in_unreleased && line.starts_with("## ") {
+            in_unreleased = false;
+        }
+        let out = if in_unreleased {
+            &mut unreleased
+        } else {
+            &mut rest
+        };
+        out.push_str(line);
+        out.push('\n');
+    }
+    (rest, unreleased)
+}
+
+/// Insert release `entry_body` (generator output) with `heading` (i.e `[1.0.0] - 2022-01-01`),
+/// folding content of `## [Unreleased]` section into it.
+///
+/// Entry is inserted at `position`, at the top it is inserted after `marker`, if present
+/// or if changelog is empty, or before the first release otherwise.
+/// `links` are maintained as link reference definitions at the bottom of changelog
+pub fn insert_release(
+    old: &str,
+    marker: &str,
+    heading: &str,
+    entry_body: &str,
+    links: &[(String, String)],
+    position: Position,
+) -> String {
+    let (old, unreleased) = take_unreleased(old);
+    let body = merge_sections(&format!(
+        "{}\n\n{}",
+        unreleased.trim(),
+        changelog::demote_headings(entry_body.trim())
+    ));
+    let entry = format!("{UNRELEASED}\n\n## {heading}\n\n{body}");
+
+    let first_release = if old.starts_with("## ") {
+        Some(0)
+    } else {
+        old.find("\n## ").map(|offset| offset + 1)
+    };
+    // New changelog gets marker, as with other formats
+    let new = if position != Position::Top || old.contains(marker) || old.trim().is_empty() {
+        changelog::insert_entry(&old, marker, &entry, position)
+    } else {
+        match first_release {
+            Some(offset) => format!(
+                "{}{}\n\n{}",
+                &old[..offset],
+                entry.trim_end(),
+                &old[offset..]
+            ),
+            None => format!("{}\n\n{}\n", old.trim_end(), entry.trim_end()),
+        }
+    };
+    if links.is_empty() {
+        return new;
+    }
+    changelog::update_links(&new, links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKER: &str = "<!-- bureaucrate goes here -->\n";
+
+    #[test]
+    fn empty_changelog() {
+        let body = "## Bugfixes\n\n- Fix\n";
+        let expected = "<!-- bureaucrate goes here -->\n\
+            ## [Unreleased]\n\n\
+            ## [1.0.0] - 2022-01-01\n\n\
+            ### Fixed\n\n\
+            - Fix\n";
+        for old in ["", "\n\n"] {
+            let new = insert_release(
+                old,
+                MARKER,
+                "[1.0.0] - 2022-01-01",
+                body,
+                &[],
+                Position::Top,
+            );
+            assert_eq!(new, expected);
+            // Second release goes after marker too
+            let new = insert_release(
+                &new,
+                MARKER,
+                "[1.1.0] - 2022-02-01",
+                body,
+                &[],
+                Position::Top,
+            );
+            assert!(new.starts_with(
+                "<!-- bureaucrate goes here -->\n## [Unreleased]\n\n## [1.1.0] - 2022-02-01\n\n"
+            ));
+            assert!(new.ends_with("## [1.0.0] - 2022-01-01\n\n### Fixed\n\n- Fix\n"));
+        }
+    }
+
+    #[test]
+    fn empty_changelog_with_links() {
+        let links = [(
+            "1.0.0".to_owned(),
+            "https://example.com/compare/v0.1.0...v1.0.0".to_owned(),
+        )];
+        let new = insert_release(
+            "",
+            MARKER,
+            "[1.0.0] - 2022-01-01",
+            "## Bugfixes\n\n- Fix\n",
+            &links,
+            Position::Top,
+        );
+        assert!(new.ends_with("- Fix\n\n[1.0.0]: https://example.com/compare/v0.1.0...v1.0.0\n"));
+    }
+}
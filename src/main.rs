@@ -1,32 +1,79 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fs,
-    path::PathBuf,
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet},
+    env,
+    ffi::OsStr,
+    fs, io, mem,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
 };
 
-use anyhow::{anyhow, Result};
-use camino::Utf8PathBuf;
-use chrono::Utc;
-use clap::{ArgGroup, Parser};
-use git2::{DiffOptions, Repository, Sort};
-use guppy::graph::{DependencyDirection, PackageMetadata};
-use jrsonnet_evaluator::{typed::Typed, FileImportResolver, State};
+use anyhow::{anyhow, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Offset, TimeZone, Utc};
+use clap::{
+    ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    ValueSource,
+};
+use clap_complete::Shell;
+use git2::{
+    Delta, DiffDelta, DiffFindOptions, DiffOptions, FileMode, Oid, Patch, Repository,
+    RepositoryOpenFlags, Sort, Status, StatusOptions,
+};
+use guppy::{
+    graph::{DependencyDirection, PackageMetadata, PackagePublish},
+    PackageId,
+};
+use rayon::prelude::*;
 use semver::Version;
 use std::fmt::Write as _;
-use tracing::{info, warn};
+use tracing::{info, info_span, warn};
+use tracing_subscriber::EnvFilter;
 
 mod bump;
-use bump::Bump;
+use bump::{BuildMetadataPolicy, Bump, BumpPolicy, DependencyBump, PrereleasePolicy};
 
-use crate::generator::Commit;
+use crate::generator::{ChangelogEntry, Commit, Evaluator, SubmoduleUpdate};
 
+mod asciidoc;
+mod atomic;
+mod cache;
+mod calver;
+mod changelog;
+mod config;
+mod conventional;
+mod edit;
 mod generator;
+mod github;
+mod keepachangelog;
+mod manifest;
+mod plan;
+mod registry;
+mod release;
+mod remote;
+mod semver_check;
+mod tags;
+mod trailers;
+use calver::Versioning;
+use config::PackageConfig;
+use edit::{Edit, Edits};
+use manifest::Manifest;
+use remote::Remote;
 
-const COMMENT_START: &str = "<!-- bureaucrate goes here -->\n";
+const COMMENT_START: &str = "<!-- bureaucrate goes here -->";
+const CHANGELOG_NAME: &str = "CHANGELOG.md";
 
 #[derive(Parser)]
 #[clap(group = ArgGroup::new("since_rev").required(true))]
+#[clap(group = ArgGroup::new("processor"))]
+#[clap(group = ArgGroup::new("mode"))]
+#[clap(subcommand_negates_reqs = true)]
 struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Last release revision
     #[clap(group = "since_rev")]
     rev: Option<String>,
@@ -34,23 +81,522 @@ struct Opts {
     /// you can't have rev pointing to parent of first commit
     #[clap(long, group = "since_rev")]
     root: bool,
-
-    /// Custom commit processor written in jsonnet
+    /// Use latest tag reachable from HEAD matching this glob (i.e `v*`)
+    /// as last release revision, version is parsed from the rest of tag name
+    #[clap(long, group = "since_rev")]
+    since_tag: Option<String>,
+    /// Use latest release tag of every package (see --tag-prefix) as its own
+    /// last release revision, packages without tags are walked from the beginning
+    #[clap(long, group = "since_rev")]
+    package_tags: bool,
+    /// Similarity percentage, starting from which deleted and added files are
+    /// considered renamed. Both sides of rename are attributed to their packages,
+    /// so moved file changes both source and destination package
+    #[clap(long, default_value = "50", value_name = "PERCENT")]
+    rename_threshold: u16,
+    /// Don't detect renames, which is faster. Moved file is then seen as deletion
+    /// and addition, which are attributed to the same packages as renames
     #[clap(long)]
-    generator: PathBuf,
+    no_rename_detection: bool,
+    /// Packages, a renamed file is attributed to. Pure moves (without content
+    /// changes) may be attributed to destination package only, so source package
+    /// doesn't get changelog entry just for file removal
+    #[clap(long, value_enum, default_value = "both")]
+    move_attribution: MoveAttribution,
+    /// Don't treat submodule pointer updates as changes of packages,
+    /// which contain these submodules
+    #[clap(long)]
+    ignore_submodules: bool,
+    /// Packages, a commit changing several of them is attributed to
+    #[clap(long, value_enum, default_value = "all")]
+    commit_attribution: CommitAttribution,
+    /// Stop history walk after this number of commits, for quick previews.
+    /// Plan is marked as truncated then
+    #[clap(long)]
+    limit: Option<usize>,
+    /// Walk revision history up to this revision instead of HEAD,
+    /// together with last release revision makes closed range
+    #[clap(long)]
+    until: Option<String>,
+    /// Only follow first parent of merge commits, so changes of merged branches
+    /// are attributed to merge commits
+    #[clap(long)]
+    first_parent: bool,
+    /// Pass merge commits to commit processor along with merged commits,
+    /// they are skipped by default (unless --first-parent is used)
+    #[clap(long)]
+    include_merges: bool,
+
+    /// Custom commit processor written in jsonnet, `-` reads it from stdin.
+    /// Either this, --generator-inline or --preset is required
+    #[clap(long, group = "processor", env = "BUREAUCRATE_GENERATOR")]
+    generator: Option<PathBuf>,
+    /// Custom commit processor code
+    #[clap(long, group = "processor", value_name = "JSONNET")]
+    generator_inline: Option<String>,
+    /// Built-in commit processor
+    #[clap(long, value_enum, group = "processor")]
+    preset: Option<Preset>,
+    /// Pass string to generator as external variable, available
+    /// via std.extVar("key")
+    #[clap(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    ext_str: Vec<(String, String)>,
+    /// Pass jsonnet code to generator as external variable, available
+    /// via std.extVar("key")
+    #[clap(long, value_name = "KEY=EXPR", value_parser = parse_key_value)]
+    ext_code: Vec<(String, String)>,
+    /// Additional library search path for generator imports
+    #[clap(long, short = 'J', value_name = "DIR")]
+    jpath: Vec<PathBuf>,
 
     /// Default mode is dry-run, add --executed to actually
     /// append changes to codebase
-    #[clap(long)]
+    #[clap(long, group = "mode")]
     execute: bool,
+    /// Only print plan without modifying anything, which is the default,
+    /// can be used to state intent explicitly
+    #[clap(long, group = "mode")]
+    dry_run: bool,
+    /// Exit with code 1 in dry-run mode, if there is any changes
+    /// or bumps required
+    #[clap(long, conflicts_with = "execute")]
+    check: bool,
+    /// Allow --execute to modify working tree with uncommitted changes
+    #[clap(long)]
+    allow_dirty: bool,
+    /// Update versions of bumped packages in Cargo.lock, along with manifests
+    #[clap(long)]
+    update_lockfile: bool,
+    /// Commit changes made by --execute
+    #[clap(long, requires = "execute")]
+    commit: bool,
+    /// Create branch at HEAD (or reuse existing one, pointing at HEAD) and
+    /// switch to it before writing changes, for pull request based releases
+    #[clap(long, requires = "execute", value_name = "NAME")]
+    branch: Option<String>,
+    /// Reset --branch to HEAD, if it already exists and points elsewhere
+    #[clap(long, requires = "branch")]
+    force_branch: bool,
+    /// Message of commit created by --commit
+    #[clap(long, default_value = "chore: release")]
+    commit_message: String,
+    /// Create annotated <package>-v<version> tag for every bumped package,
+    /// pointing at commit created by --commit
+    #[clap(long, requires = "commit")]
+    tag: bool,
+
+    /// Only release these packages, all of them by default
+    #[clap(long, short = 'p', value_name = "NAME")]
+    package: Vec<String>,
+    /// Don't release these packages
+    #[clap(long, value_name = "NAME")]
+    exclude: Vec<String>,
+
+    /// Cache collected commits in this file, cache is reused while
+    /// walked revisions, packages and commit filtering options are the same
+    #[clap(long)]
+    cache: Option<Utf8PathBuf>,
+
+    /// Path to the repository, also used as working directory
+    /// for cargo metadata. Repository is discovered like git does, so it may
+    /// be a subdirectory or a linked worktree
+    #[clap(long, default_value = ".")]
+    repo_path: PathBuf,
+
+    /// Remote, which URL is used to build commit and comparison links
+    #[clap(long, default_value = "origin")]
+    remote: String,
+
+    /// Line in changelog, after which new entries are inserted,
+    /// it is added to the top of changelog if missing
+    #[clap(long, default_value = COMMENT_START, env = "BUREAUCRATE_INSERTION_MARKER")]
+    insertion_marker: String,
+    /// Where new entries are inserted relative to insertion marker,
+    /// bottom and before-marker keep changelog in chronological order
+    #[clap(long, value_enum, default_value = "top")]
+    insert_position: changelog::Position,
+
+    /// Changelog file path, relative to package directory
+    #[clap(
+        long,
+        default_value = CHANGELOG_NAME,
+        env = "BUREAUCRATE_CHANGELOG_NAME"
+    )]
+    changelog_name: Utf8PathBuf,
+    /// Format of release date in changelog, in strftime syntax
+    #[clap(long, default_value = "%Y-%m-%d", env = "BUREAUCRATE_DATE_FORMAT")]
+    date_format: String,
+    /// Where to take release date from, last-commit uses committer date of
+    /// the last walked revision (--until or HEAD), which is reproducible
+    #[clap(long, value_enum, default_value = "now")]
+    date_source: DateSource,
+    /// Timezone of release date: local, utc or fixed offset (+HH:MM)
+    #[clap(long, default_value = "utc")]
+    timezone: Timezone,
+    /// Prefix of release tags, `{name}` is replaced with package name.
+    /// May be overridden per package with `tag_prefix` metadata
+    #[clap(long, default_value = "{name}-v", env = "BUREAUCRATE_TAG_PREFIX")]
+    tag_prefix: String,
+    /// Format of changelog entries written by --execute
+    #[clap(long, value_enum, default_value = "markdown")]
+    changelog_format: ChangelogFormat,
+
+    /// Major bump of 0.x version releases 1.0.0,
+    /// instead of bumping minor version
+    #[clap(long)]
+    promote_major: bool,
+    /// How bumps (including ones propagated from dependencies)
+    /// are applied to pre-release versions
+    #[clap(long, value_enum, default_value = "core")]
+    prerelease_policy: PrereleasePolicy,
+    /// Build metadata of bumped version: keep, strip or set:VALUE
+    #[clap(long, default_value = "strip")]
+    build_metadata: BuildMetadataPolicy,
+    /// Versioning scheme: semver, or calver:FORMAT, where format is
+    /// YYYY.MM.PATCH or YY.MM.PATCH. With calver any bump produces version
+    /// from release date, which patch counter is reset when date parts change
+    #[clap(long, default_value = "semver")]
+    versioning: Versioning,
+
+    /// Verbosity of logs, RUST_LOG overrides it when set
+    #[clap(long, value_enum, default_value = "info")]
+    log_level: LogLevel,
+    /// Format of logs, json includes fields of current span and its parents
+    #[clap(long, value_enum, default_value = "text")]
+    log_format: LogFormat,
+    /// Only log errors, same as --log-level error
+    #[clap(long, short = 'q', conflicts_with = "log_level")]
+    quiet: bool,
+
+    /// Bump package at least to this level (none, patch, minor or major),
+    /// regardless of commits, bump is propagated to dependents as usual
+    #[clap(long, value_name = "PACKAGE=LEVEL", value_parser = parse_force_bump)]
+    force_bump: Vec<(String, Bump)>,
+    /// Version, which bumps are applied to
+    #[clap(long, value_enum, default_value = "manifest")]
+    baseline: Baseline,
+    /// With --baseline git-tag, use manifest version of packages without
+    /// release tags instead of failing
+    #[clap(long)]
+    allow_untagged: bool,
+
+    /// Number of threads for commit processing, defaults to number of CPUs
+    #[clap(long, short = 'j')]
+    jobs: Option<usize>,
+
+    /// Raise bump of changed packages to major, if cargo-semver-checks
+    /// finds breaking API changes since last release revision
+    /// (or since version published to registry with --root)
+    #[clap(long)]
+    semver_check: bool,
+    /// Don't bump dependents of bumped packages, so every package bump
+    /// is decided by its own commits only. Nested packages are still bumped together
+    #[clap(long)]
+    no_propagate: bool,
+    /// Bump of dependents, caused by bump of their dependency
+    #[clap(
+        long,
+        value_enum,
+        default_value = "patch",
+        env = "BUREAUCRATE_DEPENDENCY_BUMP"
+    )]
+    dependency_bump: DependencyBump,
+    /// Also bump packages, which depend on bumped package only
+    /// as dev-dependency
+    #[clap(long)]
+    propagate_dev_deps: bool,
+    /// Let bumps of internal (`publish = false`) packages propagate to
+    /// their dependents
+    #[clap(long)]
+    propagate_internal: bool,
+
+    /// Skip commits, which author name or email matches pattern, i.e
+    /// `dependabot` or `*[bot]@users.noreply.github.com`. Matching is
+    /// case-insensitive, patterns without `*` or `?` match substrings
+    #[clap(long, value_name = "PATTERN")]
+    exclude_author: Vec<String>,
+    /// Skip commits with non utf-8 message or author, instead of
+    /// replacing invalid sequences
+    #[clap(long)]
+    skip_non_utf8: bool,
+    /// Warn about packages, which have changes, but got empty changelog
+    /// (i.e. because of mislabeled commits), with --check also fail
+    #[clap(long)]
+    require_changelog: bool,
+    /// Only compute and apply version bumps, changelog returned by generator
+    /// is ignored, and changelog files are never written
+    #[clap(long, conflicts_with = "require_changelog")]
+    no_changelog: bool,
+    /// Only write changelogs, manifests (and Cargo.lock) are never modified.
+    /// Changelog headings still use computed version, which is expected
+    /// to be set by another tool, such as cargo-release
+    #[clap(long, conflicts_with_all = &["no_changelog", "update_lockfile"])]
+    no_version_bump: bool,
+    /// Commits containing this marker as a separate line, or at the end
+    /// of title, are not passed to commit processor
+    #[clap(long, default_value = "[skip changelog]")]
+    skip_marker: String,
+
+    /// Don't treat commits, which only change `[package].version` in package
+    /// own Cargo.toml (i.e previous release commits), as package changes.
+    /// Changelog and Cargo.lock changes of such commits are ignored too
+    #[clap(long)]
+    skip_version_bumps: bool,
+
+    /// Format of dry-run plan
+    #[clap(long, value_enum, default_value = "markdown")]
+    format: Format,
+    /// Post dry-run plan as comment on GitHub pull request, updating previous
+    /// one if present. Requires GITHUB_TOKEN and GITHUB_REPOSITORY variables
+    #[clap(long, conflicts_with = "execute")]
+    github_comment: bool,
+    /// Pull request number for --github-comment, parsed from GITHUB_REF if
+    /// not specified
+    #[clap(long, requires = "github_comment")]
+    pr: Option<u64>,
+    /// Don't append dry-run plan to GitHub Actions job summary,
+    /// which is done when GITHUB_STEP_SUMMARY is set
+    #[clap(long)]
+    no_step_summary: bool,
+    /// Write dry-run plan to file instead of stdout. Accepted by --execute too,
+    /// so the same arguments can be reused, file isn't considered uncommitted change then
+    #[clap(long)]
+    output: Option<Utf8PathBuf>,
+    /// Print unified diff of changelogs and manifests, which --execute would write,
+    /// after dry-run plan
+    #[clap(long, conflicts_with = "execute")]
+    show_diff: bool,
+    /// Write changes, which --execute would make, as one `git apply` compatible
+    /// patch per file into this directory, without modifying working tree
+    #[clap(long, conflicts_with = "execute", value_name = "DIR")]
+    emit_patches: Option<Utf8PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print shell completion script to stdout
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Preset {
+    /// Conventional commits, same as example generator.jsonnet
+    Conventional,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Markdown,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DateSource {
+    Now,
+    LastCommit,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Baseline {
+    /// Version in package manifest. If it is already bumped since last release
+    /// revision (i.e by retried --execute), it isn't bumped again
+    Manifest,
+    /// Highest version published to crates.io, manifest version
+    /// is used for unpublished packages
+    CratesIo,
+    /// Latest release tag (see --tag-prefix) reachable from walked revision
+    GitTag,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+impl LogLevel {
+    fn directive(self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Human-readable
+    Text,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+enum CommitAttribution {
+    /// Commit is listed in changelog of every package it changes
+    All,
+    /// Commit is only listed in changelog of package with the deepest
+    /// directory among changed files
+    Primary,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+enum MoveAttribution {
+    /// Both source and destination packages are changed
+    Both,
+    /// Source package is only changed if file content was changed too
+    Destination,
+}
+
+#[derive(Clone, Copy)]
+enum Timezone {
+    Local,
+    Fixed(FixedOffset),
+}
+impl FromStr for Timezone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.eq_ignore_ascii_case("utc") {
+            return Ok(Self::Fixed(Utc.fix()));
+        }
+        let (sign, rest) = match s.as_bytes().first() {
+            Some(b'+') => (1, &s[1..]),
+            Some(b'-') => (-1, &s[1..]),
+            _ => return Err(anyhow!("expected local, utc or +HH:MM offset")),
+        };
+        let (hours, minutes) = rest
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected +HH:MM offset"))?;
+        let seconds = hours.parse::<i32>()? * 3600 + minutes.parse::<i32>()? * 60;
+        let offset = FixedOffset::east_opt(sign * seconds)
+            .ok_or_else(|| anyhow!("offset {s} is out of range"))?;
+        Ok(Self::Fixed(offset))
+    }
+}
+impl Timezone {
+    fn convert(self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let offset = match self {
+            Self::Local => time.with_timezone(&Local).offset().fix(),
+            Self::Fixed(offset) => offset,
+        };
+        time.with_timezone(&offset)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ChangelogFormat {
+    /// Generator output under `## [vX.Y.Z] date` heading
+    Markdown,
+    /// Keep a Changelog: `### Added/Changed/Fixed/...` sections, `[Unreleased]`
+    /// section folded into release, link reference definitions
+    #[clap(name = "keepachangelog")]
+    KeepAChangelog,
+    /// Generator output under `== vX.Y.Z date` heading, written to CHANGELOG.adoc
+    /// with AsciiDoc comment as insertion marker by default
+    #[clap(name = "asciidoc")]
+    AsciiDoc,
 }
 impl Opts {
-    fn since_rev(&self) -> Option<String> {
+    /// Parse command line, filling options not specified there from config file
+    fn load() -> Result<Self> {
+        let matches = Self::command().get_matches();
+        let mut opts = Self::from_arg_matches(&matches)?;
+        if let Some(Command::Completions { shell }) = opts.command {
+            let mut command = Self::command();
+            let name = command.get_name().to_owned();
+            clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+            process::exit(0);
+        }
+        if let Some(config) = config::find(&opts.repo_path)? {
+            opts.merge_config(&matches, config);
+        }
+        // AsciiDoc has its own defaults, unless file name and marker are set explicitly
+        if matches!(opts.changelog_format, ChangelogFormat::AsciiDoc) {
+            if opts.changelog_name == CHANGELOG_NAME {
+                opts.changelog_name = asciidoc::FILE_NAME.into();
+            }
+            if opts.insertion_marker == COMMENT_START {
+                opts.insertion_marker = asciidoc::COMMENT.to_owned();
+            }
+        }
+        if opts.generator.is_none() && opts.generator_inline.is_none() && opts.preset.is_none() {
+            return Err(anyhow!(
+                "either --generator, --generator-inline or --preset is required, generator may also be set in {}",
+                config::FILE_NAME
+            ));
+        }
+        Ok(opts)
+    }
+    fn merge_config(&mut self, matches: &ArgMatches, config: config::Config) {
+        // Command line and environment take precedence over config file
+        let from_file = |id| {
+            !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        if self.preset.is_none() && self.generator.is_none() && self.generator_inline.is_none() {
+            self.generator = config.generator;
+        }
+        if let Some(v) = config
+            .insertion_marker
+            .filter(|_| from_file("insertion_marker"))
+        {
+            self.insertion_marker = v;
+        }
+        if let Some(v) = config
+            .changelog_name
+            .filter(|_| from_file("changelog_name"))
+        {
+            self.changelog_name = v;
+        }
+        if let Some(v) = config
+            .dependency_bump
+            .filter(|_| from_file("dependency_bump"))
+        {
+            self.dependency_bump = v;
+        }
+        if let Some(v) = config.date_format.filter(|_| from_file("date_format")) {
+            self.date_format = v;
+        }
+        if let Some(v) = config.tag_prefix.filter(|_| from_file("tag_prefix")) {
+            self.tag_prefix = v;
+        }
+    }
+    /// Whether package is selected with --package and --exclude. Not selected packages
+    /// are still processed, so they can cause bumps of selected ones
+    fn is_selected(&self, name: &str) -> bool {
+        (self.package.is_empty() || self.package.iter().any(|p| p == name))
+            && !self.exclude.iter().any(|p| p == name)
+    }
+    fn until_rev(&self, repo: &Repository) -> Result<Oid> {
+        let rev = self.until.as_deref().unwrap_or("HEAD");
+        Ok(repo.revparse_single(rev)?.peel_to_commit()?.id())
+    }
+    fn since_rev(&self, repo: &Repository, until: Oid) -> Result<Option<Oid>> {
         if let Some(rev) = &self.rev {
-            Some(rev.clone())
+            Ok(Some(repo.revparse_single(rev)?.peel_to_commit()?.id()))
+        } else if let Some(pattern) = &self.since_tag {
+            let (version, id) = tags::latest_tag(repo, pattern, until)?.ok_or_else(|| {
+                anyhow!("no tags matching {pattern} are reachable from {until}, use --root instead")
+            })?;
+            info!("using {version} ({id}) as last release");
+            Ok(Some(id))
         } else {
-            assert!(self.root);
-            None
+            assert!(self.root || self.package_tags);
+            Ok(None)
         }
     }
 }
@@ -58,46 +604,706 @@ impl Opts {
 #[derive(Debug)]
 struct PackageStatus<'g> {
     changelog: String,
+    /// Structured changelog entries, if generator returned them
+    entries: Vec<ChangelogEntry>,
     bump: Bump,
     bump_reasons: Vec<String>,
+    /// Exact version requested by generator, overrides bump
+    version: Option<Version>,
+    /// Version, which bump is applied to, manifest one unless --baseline is set
+    base_version: Version,
+    /// Version to release, computed once bumps are propagated
+    final_version: Version,
+    /// Internal (`publish = false`) packages never get changelog or version bump
+    publish: bool,
+    config: PackageConfig,
     package: PackageMetadata<'g>,
 }
 impl PackageStatus<'_> {
-    fn final_version(&self) -> Version {
-        self.bump.apply(self.package.version())
+    /// Bump reasons are listed once, even if found multiple times during propagation
+    fn add_bump_reason(&mut self, reason: String) {
+        if !self.bump_reasons.contains(&reason) {
+            self.bump_reasons.push(reason);
+        }
+    }
+    fn changelog_path(&self, default_name: &Utf8Path) -> Utf8PathBuf {
+        let mut path = self.package.manifest_path().to_path_buf();
+        path.pop();
+        path.push(self.config.changelog.as_deref().unwrap_or(default_name));
+        path
+    }
+    fn tag_prefix(&self, global: &str) -> String {
+        self.config
+            .tag_prefix
+            .as_deref()
+            .unwrap_or(global)
+            .replace("{name}", self.package.name())
+    }
+    fn is_bumped(&self) -> bool {
+        self.bump > Bump::None || self.version.is_some()
+    }
+    fn resolve_final_version(
+        &mut self,
+        policy: &BumpPolicy,
+        versioning: &Versioning,
+        date: NaiveDate,
+    ) {
+        self.final_version = match &self.version {
+            Some(version) => version.clone(),
+            // Manifest is kept as is, even if baseline differs from it
+            None if self.bump == Bump::None => self.package.version().clone(),
+            None => bumped_version(self.bump, &self.base_version, policy, versioning, date),
+        };
+    }
+}
+
+/// Version after applying `bump` to `base`
+fn bumped_version(
+    bump: Bump,
+    base: &Version,
+    policy: &BumpPolicy,
+    versioning: &Versioning,
+    date: NaiveDate,
+) -> Version {
+    match versioning {
+        Versioning::Semver => bump.apply(base, policy),
+        Versioning::CalVer(calver) => calver.next(base, date),
+    }
+}
+
+/// Whether `manifest` version is already the `bump` of `released` one, i.e it was
+/// written by retried --execute. Pre-release manifest is never considered bumped,
+/// as it may be a dev version kept between releases
+fn is_bumped_since(
+    released: &Version,
+    manifest: &Version,
+    bump: Bump,
+    policy: &BumpPolicy,
+    versioning: &Versioning,
+    date: NaiveDate,
+) -> bool {
+    released != manifest
+        && manifest.pre.is_empty()
+        && &bumped_version(bump, released, policy, versioning, date) == manifest
+}
+
+fn parse_force_bump(s: &str) -> Result<(String, Bump)> {
+    let (name, bump) = parse_key_value(s)?;
+    Ok((name, bump.parse()?))
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected KEY=VALUE, got {s:?}"))?;
+    if key.is_empty() {
+        return Err(anyhow!("empty key in {s:?}"));
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Git timestamp in its original timezone
+fn git_datetime(time: git2::Time) -> DateTime<FixedOffset> {
+    FixedOffset::east_opt(time.offset_minutes() * 60)
+        .expect("git offset is less than a day")
+        .timestamp_opt(time.seconds(), 0)
+        .single()
+        .expect("fixed offset has no ambiguous time")
+}
+
+/// Formats git timestamp as RFC3339, keeping original timezone offset
+fn format_git_time(time: git2::Time) -> String {
+    git_datetime(time).to_rfc3339()
+}
+
+/// Result of commit processing for single package
+struct Processed {
+    /// Titles of processed commits
+    subjects: Vec<String>,
+    changelog: String,
+    entries: Vec<ChangelogEntry>,
+    bump: Bump,
+    /// Validated exact version, requested by generator
+    version: Option<Version>,
+    /// cargo-semver-checks found breaking changes
+    breaking: bool,
+}
+
+/// Run commit processor, and semver checks if requested, for package.
+/// Called from worker threads, so its results are applied to statuses later
+fn process_package(
+    opts: &Opts,
+    evaluator: Option<&Evaluator>,
+    remote: &Option<Remote>,
+    pkg: PackageMetadata<'_>,
+    publish: bool,
+    baseline: Option<Oid>,
+    commits: Vec<Commit>,
+) -> Result<Processed> {
+    let _span = info_span!("package", name = %pkg.name()).entered();
+    let changed = !commits.is_empty();
+    let subjects = commits
+        .iter()
+        .map(|c| c.message.lines().next().unwrap_or_default().to_owned())
+        .collect();
+    let (changelog, entries, bump, version) = if let Some(evaluator) = evaluator {
+        let s = &evaluator.state;
+        let package = generator::Package {
+            name: pkg.name().to_owned(),
+            current_version: pkg.version().to_string(),
+            repository_url: remote.as_ref().map(|r| r.base().to_owned()),
+        };
+        let verdict = info_span!("generator")
+            .in_scope(|| {
+                evaluator
+                    .generator
+                    .commit_handler
+                    .call(s.clone(), commits, package)
+            })
+            .map_err(|e| evaluator.error(&format!("commitHandler for {}", pkg.name()), &e))?;
+        let entries = verdict.entries.unwrap_or_default();
+        let mut changelog = changelog::render_entries(&entries);
+        if let Some(text) = &verdict.changelog {
+            changelog.push_str(text);
+        }
+        (
+            changelog,
+            entries,
+            Bump::from_raw(&verdict.bump)
+                .with_context(|| format!("generator returned invalid bump for {}", pkg.name()))?,
+            verdict.version,
+        )
+    } else {
+        match opts.preset.expect("processor is required") {
+            Preset::Conventional => {
+                let (changelog, bump) = conventional::commit_handler(&commits)?;
+                (changelog, vec![], bump, None)
+            }
+        }
+    };
+
+    let version = match version {
+        Some(version) => {
+            let version = Version::parse(&version).with_context(|| {
+                format!("generator returned invalid version for {}", pkg.name())
+            })?;
+            if &version <= pkg.version() {
+                return Err(anyhow!(
+                    "generator returned version {version} for {}, which is not greater than current {}",
+                    pkg.name(),
+                    pkg.version()
+                ));
+            }
+            Some(version)
+        }
+        None => None,
+    };
+    let breaking = opts.semver_check
+        && changed
+        && publish
+        && bump < Bump::Major
+        && semver_check::is_breaking(pkg.manifest_path(), pkg.name(), baseline)?;
+    Ok(Processed {
+        subjects,
+        changelog,
+        entries,
+        bump,
+        version,
+        breaking,
+    })
+}
+
+/// Raise bumps of packages, which inherit workspace version, to the highest one
+/// among them, as they are released with the same version. Returns whether
+/// any bump was raised
+fn equalize_inherited<'g>(
+    statuses: &mut HashMap<&'g PackageId, PackageStatus<'g>>,
+    inheriting: &[&'g PackageId],
+) -> bool {
+    let leader = match inheriting.iter().max_by_key(|id| statuses[*id].bump) {
+        Some(id) => statuses[id].package,
+        None => return false,
+    };
+    let bump = statuses[leader.id()].bump;
+    let mut raised = false;
+    for id in inheriting {
+        let status = statuses.get_mut(id).expect("there is all packages");
+        if status.bump < bump {
+            status.add_bump_reason(format!(
+                "{} inherits workspace version, bumped to match {bump:?} bump of {}",
+                status.package.name(),
+                leader.name()
+            ));
+            status.bump = bump;
+            raised = true;
+        }
+    }
+    raised
+}
+
+/// Whether bump of `dependency` is propagated to `dependent`
+fn propagates_to(opts: &Opts, dependent: PackageMetadata<'_>, dependency: &PackageId) -> bool {
+    dependent
+        .direct_links()
+        .filter(|link| link.to().id() == dependency)
+        .any(|link| {
+            // Dev-dependencies don't affect published artifact
+            link.normal().is_present()
+                || link.build().is_present()
+                || (opts.propagate_dev_deps && link.dev().is_present())
+        })
+}
+
+/// Whether commit message has `marker` as a whole line, or at the end of title,
+/// case-insensitive
+fn has_skip_marker(message: &str, marker: &str) -> bool {
+    let marker = marker.trim().to_lowercase();
+    if marker.is_empty() {
+        return false;
+    }
+    let mut lines = message.lines().map(|l| l.trim().to_lowercase());
+    let title = lines.next().unwrap_or_default();
+    title == marker || title.ends_with(&format!(" {marker}")) || lines.any(|line| line == marker)
+}
+
+/// Whether `delta` of manifest only changes its `[package].version`. Both sides
+/// are parsed, and compared without it, so any other change (including
+/// dependency requirements and `[workspace.package].version`) counts
+fn is_version_only(repo: &Repository, delta: &DiffDelta<'_>) -> Result<bool> {
+    if delta.status() != Delta::Modified {
+        return Ok(false);
+    }
+    let without_version = |id: Oid| -> Result<Option<String>> {
+        let blob = repo.find_blob(id)?;
+        let mut document = match std::str::from_utf8(blob.content())
+            .ok()
+            .and_then(|text| text.parse::<toml_edit::Document>().ok())
+        {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+        if let Some(package) = document
+            .get_mut("package")
+            .and_then(toml_edit::Item::as_table_like_mut)
+        {
+            package.remove("version");
+        }
+        Ok(Some(document.to_string()))
+    };
+    let old = without_version(delta.old_file().id())?;
+    Ok(old.is_some() && old == without_version(delta.new_file().id())?)
+}
+
+/// Case-insensitive match of author name or email, pattern with `*` or `?`
+/// matches whole field, otherwise it is searched as substring
+fn author_matches(field: &str, pattern: &str) -> bool {
+    fn glob(text: &[char], pattern: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => (0..=text.len()).any(|skip| glob(&text[skip..], rest)),
+            Some((c, rest)) => match text.split_first() {
+                Some((t, text)) => (*c == '?' || c == t) && glob(text, rest),
+                None => false,
+            },
+        }
+    }
+    let field = field.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    if pattern.contains(['*', '?']) {
+        let field: Vec<_> = field.chars().collect();
+        let pattern: Vec<_> = pattern.chars().collect();
+        glob(&field, &pattern)
+    } else {
+        field.contains(&pattern)
+    }
+}
+
+/// Package directory relative to repository root
+fn package_dir(
+    pkg: &PackageMetadata<'_>,
+    workspace_root: &Utf8Path,
+    workspace_dir: &Utf8Path,
+) -> Result<Utf8PathBuf> {
+    let path = match pkg.source().workspace_path() {
+        Some(path) => path.to_owned(),
+        None => {
+            let manifest_dir = pkg
+                .manifest_path()
+                .parent()
+                .expect("manifest is located in directory");
+            manifest_dir
+                .strip_prefix(workspace_root)
+                .map_err(|_| anyhow!("package {} is outside of workspace", pkg.name()))?
+                .to_owned()
+        }
+    };
+    // Avoid trailing slash for package located at the workspace root
+    if path.as_str().is_empty() {
+        return Ok(workspace_dir.to_owned());
     }
+    Ok(workspace_dir.join(path))
+}
+
+fn init_logging(opts: &Opts) {
+    let level = if opts.quiet {
+        LogLevel::Error
+    } else {
+        opts.log_level
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level.directive()));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match opts.log_format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+/// Working directory of repository, which diff paths are relative to
+fn repo_root(repo: &Repository) -> Result<Utf8PathBuf> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("bare repositories are not supported"))?;
+    Utf8PathBuf::from_path_buf(workdir.to_path_buf())
+        .map_err(|path| anyhow!("{} is not utf-8", path.display()))
+}
+
+/// Uncommitted and untracked files, relative to repository root. Files `written`
+/// by dry-run (--cache and --output) are not listed, as they may be placed inside
+/// of repository
+fn dirty_files(repo: &Repository, written: &[&Utf8PathBuf]) -> Result<Vec<String>> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("bare repositories are not supported"))?
+        .canonicalize()?;
+    // Missing files can't be dirty
+    let written: Vec<_> = written
+        .iter()
+        .filter_map(|path| path.as_std_path().canonicalize().ok())
+        .filter_map(|path| Some(path.strip_prefix(&workdir).ok()?.to_owned()))
+        .collect();
+    let mut status_opts = StatusOptions::new();
+    status_opts.include_untracked(true).include_ignored(false);
+    Ok(repo
+        .statuses(Some(&mut status_opts))?
+        .iter()
+        .filter(|entry| entry.status() != Status::CURRENT)
+        .filter_map(|entry| entry.path().map(ToOwned::to_owned))
+        .filter(|path| !written.iter().any(|written| written == Path::new(path)))
+        .collect())
+}
+
+fn read_changelog(path: &Utf8Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {path}")),
+    }
+}
+
+/// Label of release heading, i.e `v1.0.0` in `## [v1.0.0] 2022-01-01`
+fn release_label(opts: &Opts, package: &PackageStatus<'_>) -> String {
+    match opts.changelog_format {
+        ChangelogFormat::Markdown => format!("v{}", package.final_version),
+        ChangelogFormat::KeepAChangelog => package.final_version.to_string(),
+        ChangelogFormat::AsciiDoc => format!("v{}", package.final_version),
+    }
+}
+
+/// Whether release with `label` is in changelog, and is the latest one,
+/// see [`changelog::find_release`]
+fn find_release(opts: &Opts, old: &str, marker: &str, label: &str) -> Option<bool> {
+    let position = opts.insert_position;
+    match opts.changelog_format {
+        ChangelogFormat::Markdown | ChangelogFormat::KeepAChangelog => {
+            changelog::find_release(old, marker, label, position)
+        }
+        ChangelogFormat::AsciiDoc => asciidoc::find_release(old, marker, label, position),
+    }
+}
+
+/// Warn about packages, which are going to be released with version already
+/// present in their changelogs, not as the latest release
+fn warn_released(opts: &Opts, packages: &[PackageStatus<'_>]) -> Result<()> {
+    let marker = format!("{}\n", opts.insertion_marker);
+    for package in packages.iter().filter(|p| !p.changelog.is_empty()) {
+        let path = package.changelog_path(&opts.changelog_name);
+        let old = read_changelog(&path)?.unwrap_or_default();
+        let label = release_label(opts, package);
+        if find_release(opts, &old, &marker, &label) == Some(false) {
+            warn!(
+                "{path} already has older {label} entry, baseline version {} of {} is likely stale",
+                package.base_version,
+                package.package.name()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Date of release in --timezone
+fn release_date(opts: &Opts, repo: &Repository, until: Oid) -> Result<DateTime<FixedOffset>> {
+    let date = match opts.date_source {
+        DateSource::Now => Utc::now().with_timezone(&Utc.fix()),
+        DateSource::LastCommit => git_datetime(repo.find_commit(until)?.committer().when()),
+    };
+    Ok(opts.timezone.convert(date))
+}
+
+/// Changelog and manifest edits, which release of `packages` consists of
+fn release_edits(
+    opts: &Opts,
+    repo: &Repository,
+    until: Oid,
+    remote: &Option<Remote>,
+    workspace_root: &Utf8Path,
+    packages: &[PackageStatus<'_>],
+    internal: &[PackageStatus<'_>],
+) -> Result<Edits> {
+    let mut edits = Edits::default();
+
+    let marker = format!("{}\n", opts.insertion_marker);
+    let date = release_date(opts, repo, until)?
+        .date_naive()
+        .format(&opts.date_format)
+        .to_string();
+    for package in packages {
+        if package.changelog.is_empty() {
+            continue;
+        }
+        let changelog_path = package.changelog_path(&opts.changelog_name);
+        let existing = read_changelog(&changelog_path)?;
+        let old_changelog = existing.clone().unwrap_or_default();
+        let label = release_label(opts, package);
+        match find_release(opts, &old_changelog, &marker, &label) {
+            // Execute may be retried, entry is only inserted once
+            Some(true) => {
+                info!("{changelog_path} already has {label} entry, skipping");
+                continue;
+            }
+            // Already warned about in dry-run
+            Some(false) if !opts.execute => continue,
+            Some(false) => {
+                return Err(anyhow!(
+                    "{changelog_path} already has older {label} entry, baseline version {} of {} is likely stale",
+                    package.base_version,
+                    package.package.name()
+                ))
+            }
+            None => {}
+        }
+        let prefix = package.tag_prefix(&opts.tag_prefix);
+        let release_tag = release::tag_name(&prefix, &package.final_version);
+        let compare_url = match remote {
+            Some(remote) if package.is_bumped() => Some(remote.compare_url(
+                &release::tag_name(&prefix, &package.base_version),
+                &release_tag,
+            )),
+            _ => None,
+        };
+        let new_changelog = match opts.changelog_format {
+            ChangelogFormat::Markdown => {
+                let mut entry = format!("## [{label}] {date}\n\n");
+                entry.push_str(&changelog::demote_headings(package.changelog.trim()));
+                let new_changelog =
+                    changelog::insert_entry(&old_changelog, &marker, &entry, opts.insert_position);
+                match compare_url {
+                    Some(url) => {
+                        let mut links = vec![(label.clone(), url)];
+                        // Previous release might be linked to unreleased changes
+                        let previous = format!("v{}", package.base_version);
+                        if let Some(url) = changelog::find_link(&new_changelog, &previous) {
+                            if let Some(base) = url.strip_suffix("...HEAD") {
+                                let tag = release::tag_name(&prefix, &package.base_version);
+                                links.push((previous, format!("{base}...{tag}")));
+                            }
+                        }
+                        changelog::update_links(&new_changelog, &links)
+                    }
+                    None => new_changelog,
+                }
+            }
+            ChangelogFormat::AsciiDoc => {
+                let mut entry = asciidoc::heading(&label, &date, compare_url.as_deref());
+                entry.push_str("\n\n");
+                entry.push_str(&asciidoc::convert_headings(package.changelog.trim()));
+                changelog::insert_entry(&old_changelog, &marker, &entry, opts.insert_position)
+            }
+            ChangelogFormat::KeepAChangelog => {
+                let mut links = vec![];
+                if let (Some(remote), Some(url)) = (remote, compare_url) {
+                    links.push((
+                        "Unreleased".to_owned(),
+                        remote.compare_url(&release_tag, "HEAD"),
+                    ));
+                    links.push((label.clone(), url));
+                }
+                keepachangelog::insert_release(
+                    &old_changelog,
+                    &marker,
+                    &format!("[{label}] - {date}"),
+                    &package.changelog,
+                    &links,
+                    opts.insert_position,
+                )
+            }
+        };
+
+        edits.push(Edit {
+            path: changelog_path,
+            old: existing,
+            new: new_changelog,
+        });
+    }
+    if opts.no_version_bump {
+        return Ok(edits);
+    }
+    let bumped_versions: HashMap<&str, Version> = packages
+        .iter()
+        .filter(|package| &package.final_version != package.package.version())
+        .map(|package| (package.package.name(), package.final_version.clone()))
+        .collect();
+    let mut workspace_version: Option<(Version, &str)> = None;
+    for package in packages {
+        let mut manifest = Manifest::open(package.package.manifest_path(), &edits)?;
+        manifest.update_requirements(&bumped_versions);
+        let final_version = package.final_version.clone();
+        if manifest.inherits_version() {
+            match &workspace_version {
+                Some((version, name)) if version != &final_version => {
+                    return Err(anyhow!(
+                        "{} and {} inherit workspace version, but should be released as {} and {}",
+                        name,
+                        package.package.name(),
+                        version,
+                        final_version,
+                    ))
+                }
+                Some(_) => {}
+                None => workspace_version = Some((final_version, package.package.name())),
+            }
+        } else if package.package.version() != &final_version {
+            manifest.set_version(&final_version)?;
+        }
+        if let Some(edit) = manifest.edit() {
+            edits.push(edit);
+        }
+    }
+    for package in internal {
+        let mut manifest = Manifest::open(package.package.manifest_path(), &edits)?;
+        manifest.update_requirements(&bumped_versions);
+        if let Some(edit) = manifest.edit() {
+            edits.push(edit);
+        }
+    }
+    // Root manifest may hold both inherited version and dependency requirements
+    let manifest_path = workspace_root.join("Cargo.toml");
+    let mut manifest = Manifest::open(&manifest_path, &edits)?;
+    manifest.update_workspace_requirements(&bumped_versions);
+    if let Some((version, _)) = workspace_version {
+        manifest.set_workspace_version(&version)?;
+    }
+    if let Some(edit) = manifest.edit() {
+        edits.push(edit);
+    }
+    if opts.update_lockfile {
+        let lockfile = workspace_root.join("Cargo.lock");
+        if !lockfile.exists() {
+            warn!("{lockfile} not found, not updating it");
+        } else if let Some(edit) = manifest::update_lockfile(&lockfile, &bumped_versions)? {
+            edits.push(edit);
+        }
+    }
+    Ok(edits)
 }
 
 fn main() -> Result<()> {
-    tracing_subscriber::fmt().init();
-    let opts = Opts::parse();
+    let opts = Opts::load()?;
+    init_logging(&opts);
 
     info!("opening repo");
-    let repo = Repository::open(".")?;
+    // Opened the same way git does, so linked worktrees and GIT_DIR/GIT_COMMON_DIR,
+    // set for hooks, are respected. Refs (including tags) and config are read
+    // from common dir by libgit2, while HEAD and index belong to the worktree
+    let repo = Repository::open_ext(
+        &opts.repo_path,
+        RepositoryOpenFlags::FROM_ENV,
+        // Read from GIT_CEILING_DIRECTORIES with FROM_ENV
+        &[] as &[&OsStr],
+    )
+    .with_context(|| format!("{} is not a git repository", opts.repo_path.display()))?;
+    if repo.is_worktree() {
+        info!(
+            "{} is a linked worktree of {}",
+            repo.path().display(),
+            repo.commondir().display()
+        );
+    }
 
     info!("searching for top-level packages");
-    let cargo_metadata = guppy::MetadataCommand::new().exec()?;
+    let cargo_metadata = guppy::MetadataCommand::new()
+        .current_dir(&opts.repo_path)
+        .exec()?;
     let metadata = cargo_metadata.build_graph()?;
 
     let mut statuses = HashMap::new();
 
+    // Git reports paths relative to repository root, while cargo reports them
+    // relative to workspace root, which may be located in subdirectory
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("bare repositories are not supported"))?
+        .canonicalize()?;
+    let workspace_root = metadata.workspace().root();
+    let workspace_dir = workspace_root
+        .as_std_path()
+        .canonicalize()?
+        .strip_prefix(&workdir)
+        .map_err(|_| anyhow!("workspace {workspace_root} is outside of repository"))?
+        .to_owned();
+    let workspace_dir = Utf8PathBuf::try_from(workspace_dir)?;
+
     let workspace = metadata.resolve_workspace();
+    for name in opts.package.iter().chain(&opts.exclude) {
+        if !workspace
+            .packages(DependencyDirection::Forward)
+            .any(|p| p.name() == name)
+        {
+            return Err(anyhow!("package {name} is not a member of workspace"));
+        }
+    }
+    // Checked before walking history, which may take a while
+    for (name, _) in &opts.force_bump {
+        if !workspace
+            .packages(DependencyDirection::Forward)
+            .any(|p| p.name() == name)
+        {
+            return Err(anyhow!(
+                "--force-bump package {name} is not a member of workspace"
+            ));
+        }
+    }
+    let mut pkgdirs = HashMap::new();
+    for pkg in workspace.packages(DependencyDirection::Forward) {
+        pkgdirs.insert(pkg.id(), package_dir(&pkg, workspace_root, &workspace_dir)?);
+    }
+
     let mut nested = HashSet::new();
     let mut nested_pairs = Vec::new();
+    // Released packages with `version.workspace = true`
+    let mut inheriting = Vec::new();
     for outer in workspace.packages(DependencyDirection::Forward) {
-        let path = outer
-            .source()
-            .workspace_path()
-            .expect("this is workspace package");
+        let path = &pkgdirs[outer.id()];
 
         for inner in workspace
             .packages(DependencyDirection::Forward)
             .filter(|inner| inner != &outer)
         {
-            let inner_dir = inner
-                .source()
-                .workspace_path()
-                .expect("this is workspace package");
+            let inner_dir = &pkgdirs[inner.id()];
 
             if !inner_dir.starts_with(path) {
                 continue;
@@ -111,133 +1317,604 @@ fn main() -> Result<()> {
             nested_pairs.push((outer.id(), inner.id()));
         }
 
+        let config = PackageConfig::from_metadata(outer.name(), outer.metadata_table())?;
+        let publish = !matches!(
+            outer.publish(),
+            PackagePublish::Registries(registries) if registries.is_empty()
+        ) && config.publish_bumps.unwrap_or(true);
+        if publish && Manifest::open(outer.manifest_path(), &Edits::default())?.inherits_version() {
+            inheriting.push(outer.id());
+        }
         statuses.insert(
             outer.id(),
             PackageStatus {
                 changelog: String::new(),
+                entries: vec![],
                 bump: Bump::None,
                 bump_reasons: vec![],
+                version: None,
+                base_version: outer.version().clone(),
+                final_version: outer.version().clone(),
+                publish,
+                config,
                 package: outer,
             },
         );
     }
-    let outers = workspace.filter(DependencyDirection::Forward, |c| !nested.contains(&c.id()));
 
-    let hide = if let Some(since) = opts.since_rev() {
-        Some(repo.revparse_single(&since)?.id())
-    } else {
-        None
-    };
+    let until = opts.until_rev(&repo)?;
+    let hide = opts.since_rev(&repo, until)?;
+    if let Some(hide) = hide {
+        let rev = opts.until.as_deref().unwrap_or("HEAD");
+        if hide == until {
+            if opts.until.is_some() {
+                return Err(anyhow!(
+                    "last release revision {hide} is {rev} itself, there is no commits to walk"
+                ));
+            }
+            info!("last release revision is {rev}, there is no commits to walk");
+        } else if !repo.graph_descendant_of(until, hide)? {
+            // Walk would include every commit not reachable from hide,
+            // which is most likely the whole history
+            let hint = match repo.merge_base(until, hide) {
+                Ok(base) => format!("their merge base is {base}, you may want to use it instead"),
+                Err(_) => "they have no common history, use --root to walk everything".to_owned(),
+            };
+            return Err(anyhow!(
+                "last release revision {hide} is not an ancestor of {rev} ({until}), {hint}"
+            ));
+        }
+    }
+
+    let mut package_dirs = vec![];
+    for pkg in workspace.packages(DependencyDirection::Forward) {
+        let pkgdir = &pkgdirs[pkg.id()];
+        let mut dirs = vec![pkgdir.to_path_buf()];
+        if let Some(v) = pkg.metadata_table().get("bureaucrate-extra-dirs") {
+            let arr = v
+                .as_array()
+                .ok_or_else(|| anyhow!("extra dirs should be a list"))?;
+            for val in arr {
+                let pathstr = val
+                    .as_str()
+                    .ok_or_else(|| anyhow!("extra dir should be a string"))?;
+                let mut path = pkgdir.to_path_buf();
+                path.push(pathstr);
+                dirs.push(path);
+            }
+        }
+        info!("checking for updates in {} ({pkgdir})", pkg.name());
+        package_dirs.push((pkg, dirs));
+    }
 
-    for pkg in outers.packages(DependencyDirection::Forward) {
-        let pkgdir = pkg
-            .source()
-            .workspace_path()
-            .expect("this is workspace package");
-        let extra_dirs: Vec<Utf8PathBuf> =
-            if let Some(v) = pkg.metadata_table().get("bureaucrate-extra-dirs") {
-                let arr = v
-                    .as_array()
-                    .ok_or_else(|| anyhow!("extra dirs should be a list"))?;
-                let mut out = vec![];
-                for val in arr {
-                    let pathstr = val
-                        .as_str()
-                        .ok_or_else(|| anyhow!("extra dir should be a string"))?;
-                    let mut path = pkgdir.to_path_buf();
-                    path.push(pathstr);
-                    out.push(path);
+    info!("walking revision history");
+    let mut walk = repo.revwalk()?;
+    walk.reset()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    walk.push(until)?;
+    if opts.first_parent {
+        walk.simplify_first_parent()?;
+    }
+    if let Some(hide) = hide {
+        walk.hide(hide)?;
+    }
+
+    // History is only walked once, pathspec makes libgit2 only report deltas
+    // touching any of package directories, either by old or by new path,
+    // then changed files are bucketed per package
+    let mut diff_opts = DiffOptions::new();
+    diff_opts
+        .old_prefix("")
+        .new_prefix("")
+        .disable_pathspec_match(true);
+    let all_dirs = package_dirs.iter().flat_map(|(_, dirs)| dirs);
+    // Empty pathspec list matches everything, which is exactly what package
+    // located at workspace root needs
+    if all_dirs.clone().all(|dir| !dir.as_str().is_empty()) {
+        for dir in all_dirs {
+            diff_opts.pathspec(dir.as_str());
+        }
+    }
+
+    let remote = Remote::find(&repo, &opts.remote);
+    // With --package-tags, every package has its own range of commits,
+    // which is collected per distinct release tag
+    let mut package_baselines = HashMap::new();
+    let mut baseline_ranges: HashMap<Oid, HashSet<Oid>> = HashMap::new();
+    if opts.package_tags {
+        for (pkg, _) in &package_dirs {
+            let prefix = statuses[pkg.id()].tag_prefix(&opts.tag_prefix);
+            let (version, baseline) = match tags::latest_tag(&repo, &format!("{prefix}*"), until)? {
+                Some(tag) => tag,
+                None => {
+                    info!(
+                        "{} has no {prefix}* tags, walking from the beginning",
+                        pkg.name()
+                    );
+                    continue;
                 }
-                out
-            } else {
-                vec![]
             };
+            if &version != pkg.version() {
+                warn!(
+                    "latest release tag of {} is {prefix}{version}, but manifest has version {}",
+                    pkg.name(),
+                    pkg.version()
+                );
+            }
+            info!(
+                "using {prefix}{version} ({baseline}) as last release of {}",
+                pkg.name()
+            );
+            package_baselines.insert(pkg.id(), baseline);
+            if baseline_ranges.contains_key(&baseline) {
+                continue;
+            }
+            let mut range_walk = repo.revwalk()?;
+            range_walk.push(until)?;
+            if opts.first_parent {
+                range_walk.simplify_first_parent()?;
+            }
+            range_walk.hide(baseline)?;
+            baseline_ranges.insert(baseline, range_walk.collect::<Result<_, _>>()?);
+        }
+    }
 
-        info!("checking for updates in {} ({pkgdir})", pkg.name());
-        let mut walk = repo.revwalk()?;
-        walk.reset()?;
-        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
-        walk.push_head()?;
-        if let Some(hide) = hide {
-            walk.hide(hide)?;
-        }
-
-        let s = State::default();
-        s.set_import_resolver(Box::new(FileImportResolver::default()));
-        s.with_stdlib();
-
-        let gen = s
-            .import(opts.generator.canonicalize()?)
-            .map_err(|e| anyhow!("{}", s.stringify_err(&e)))?;
-        let gen = generator::Generator::from_untyped(gen, s.clone())
-            .map_err(|e| anyhow!("{}", s.stringify_err(&e)))?;
-
-        let mut commits = vec![];
-        for rev in walk {
+    let mut find_opts = DiffFindOptions::new();
+    find_opts
+        .renames(true)
+        .rename_threshold(opts.rename_threshold);
+
+    let mailmap = repo.mailmap()?;
+    let mut package_commits: HashMap<_, Vec<Commit>> = HashMap::new();
+    // Everything commit collection depends on, besides repository contents
+    let cache_key = cache::key((
+        until.to_string(),
+        hide.map(|hide| hide.to_string()),
+        package_dirs
+            .iter()
+            .map(|(pkg, dirs)| (pkg.id().repr(), dirs))
+            .collect::<Vec<_>>(),
+        {
+            let mut baselines: Vec<_> = package_baselines
+                .iter()
+                .map(|(id, baseline)| (id.repr(), baseline.to_string()))
+                .collect();
+            baselines.sort();
+            baselines
+        },
+        (
+            opts.first_parent,
+            opts.include_merges,
+            opts.skip_version_bumps,
+            opts.skip_non_utf8,
+        ),
+        (
+            &opts.skip_marker,
+            &opts.exclude_author,
+            &opts.remote,
+            &opts.changelog_name,
+        ),
+        opts.limit,
+        (
+            opts.rename_threshold,
+            opts.no_rename_detection,
+            opts.move_attribution,
+            opts.commit_attribution,
+            opts.ignore_submodules,
+        ),
+    ));
+    // History walk was stopped by --limit
+    let mut truncated = false;
+    let cached = opts
+        .cache
+        .as_ref()
+        .and_then(|path| cache::load(path, &cache_key));
+    if let Some((mut cached, cached_truncated)) = cached {
+        truncated = cached_truncated;
+        for (pkg, _) in &package_dirs {
+            if let Some(commits) = cached.remove(pkg.id().repr()) {
+                package_commits.insert(pkg.id(), commits);
+            }
+        }
+    } else {
+        let _span = info_span!("revwalk").entered();
+        for (walked, rev) in walk.enumerate() {
+            if opts.limit == Some(walked) {
+                warn!("stopping history walk after {walked} commits, changelog is incomplete");
+                truncated = true;
+                break;
+            }
             let rev = rev?;
             let commit = repo.find_commit(rev)?;
             let commit_tree = commit.tree()?;
 
-            let mut changed = false;
-            for parent in commit.parents() {
+            // Sorted and deduplicated across parents of merge commits
+            let mut files = BTreeSet::new();
+            let mut version_only_files = HashMap::new();
+            // Insertions and deletions by new path
+            let mut file_stats = HashMap::new();
+            // Gitlink changes, compared to first parent
+            let mut submodules = vec![];
+            // Merge commits are skipped by default, as their changes are attributed
+            // to merged commits
+            if commit.parent_count() > 1 && !opts.include_merges && !opts.first_parent {
+                continue;
+            }
+            // Included merge commit is compared to the mainline only,
+            // so it includes all changes of merged branch
+            let parent_limit = if opts.first_parent || opts.include_merges {
+                1
+            } else {
+                usize::MAX
+            };
+            for (parent_idx, parent) in commit.parents().take(parent_limit).enumerate() {
                 let tree = parent.tree()?;
-                let mut opts = DiffOptions::new();
-                let mut diff = repo.diff_tree_to_tree(
-                    Some(&tree),
-                    Some(&commit_tree),
-                    Some(opts.old_prefix("").new_prefix("")),
-                )?;
-                diff.find_similar(None)?;
-                // TODO: use pathspec matcher, instead of naive delta iteration
-                for diff in diff.deltas() {
-                    'diff_files: for file in [diff.old_file().path(), diff.new_file().path()]
-                        .into_iter()
-                        .flatten()
-                    {
-                        if file.starts_with(pkgdir.as_std_path()) {
-                            changed = true;
-                            break;
-                        }
-                        for dir in &extra_dirs {
-                            if file.starts_with(dir.as_std_path()) {
-                                changed = true;
-                                break 'diff_files;
-                            }
+                let mut diff =
+                    repo.diff_tree_to_tree(Some(&tree), Some(&commit_tree), Some(&mut diff_opts))?;
+                if !opts.no_rename_detection {
+                    diff.find_similar(Some(&mut find_opts))?;
+                }
+                for (idx, delta) in diff.deltas().enumerate() {
+                    let is_gitlink = [delta.old_file().mode(), delta.new_file().mode()]
+                        .contains(&FileMode::Commit);
+                    if is_gitlink && opts.ignore_submodules {
+                        continue;
+                    }
+                    if is_gitlink && parent_idx == 0 {
+                        let path = delta
+                            .new_file()
+                            .path()
+                            .or_else(|| delta.old_file().path())
+                            .expect("delta has path");
+                        let id = |id: Oid| (!id.is_zero()).then(|| id.to_string());
+                        submodules.push(SubmoduleUpdate {
+                            path: path
+                                .to_str()
+                                .ok_or_else(|| anyhow!("utf-8 path"))?
+                                .to_owned(),
+                            old_id: id(delta.old_file().id()),
+                            new_id: id(delta.new_file().id()),
+                        });
+                    }
+                    let is_manifest = delta.new_file().path().map(|p| p.ends_with("Cargo.toml"));
+                    let version_only = opts.skip_version_bumps
+                        && is_manifest == Some(true)
+                        && is_version_only(&repo, &delta)?;
+                    let pure_move = delta.status() == Delta::Renamed
+                        && delta.old_file().id() == delta.new_file().id();
+                    let old_path =
+                        if pure_move && opts.move_attribution == MoveAttribution::Destination {
+                            None
+                        } else {
+                            delta.old_file().path()
+                        };
+                    for file in [old_path, delta.new_file().path()].into_iter().flatten() {
+                        let file = file.to_str().ok_or_else(|| anyhow!("utf-8 path"))?;
+                        files.insert(file.to_owned());
+                        // File is only considered version-only if it is such
+                        // compared to every parent
+                        *version_only_files.entry(file.to_owned()).or_insert(true) &= version_only;
+                    }
+                    // Merge commit stats are counted against first parent, which
+                    // is what merge introduces to mainline
+                    if parent_idx == 0 {
+                        let file = delta.new_file().path().expect("delta has path");
+                        let file = file.to_str().ok_or_else(|| anyhow!("utf-8 path"))?;
+                        if let Some(patch) = Patch::from_diff(&diff, idx)? {
+                            let (_, insertions, deletions) = patch.line_stats()?;
+                            file_stats.insert(file.to_owned(), (insertions, deletions));
                         }
                     }
                 }
             }
-            if changed {
-                let message = commit.message().ok_or_else(|| anyhow!("expected utf-8"))?;
-                let author = commit.author_with_mailmap(&repo.mailmap()?)?;
-                let id = commit.id();
-                commits.push(Commit {
-                    id: id.to_string(),
-                    author_email: author
-                        .email()
-                        .ok_or_else(|| anyhow!("utf-8 email"))?
-                        .to_owned(),
-                    author_name: author
-                        .name()
-                        .ok_or_else(|| anyhow!("utf-8 name"))?
-                        .to_owned(),
-                    message: message.to_owned(),
-                })
+            if files.is_empty() {
+                continue;
+            }
+
+            let author = commit.author_with_mailmap(&mailmap)?;
+            let committer = commit.committer_with_mailmap(&mailmap)?;
+            // Legacy repositories may have commits in other encodings
+            if commit.message().is_none()
+                || [&author, &committer]
+                    .iter()
+                    .any(|s| s.name().is_none() || s.email().is_none())
+            {
+                if opts.skip_non_utf8 {
+                    warn!("skipping {rev}, it has non utf-8 message, author or committer");
+                    continue;
+                }
+                warn!("{rev} has non utf-8 message, author or committer, invalid sequences are replaced");
+            }
+            let message = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+            let author_name = String::from_utf8_lossy(author.name_bytes()).into_owned();
+            let author_email = String::from_utf8_lossy(author.email_bytes()).into_owned();
+            let message_trailers = trailers::parse(&message);
+            let committer_name = String::from_utf8_lossy(committer.name_bytes()).into_owned();
+            let committer_email = String::from_utf8_lossy(committer.email_bytes()).into_owned();
+
+            if has_skip_marker(&message, &opts.skip_marker) {
+                info!("skipping {rev}, marked with {}", opts.skip_marker);
+                continue;
+            }
+            if let Some(pattern) = opts.exclude_author.iter().find(|pattern| {
+                author_matches(&author_name, pattern) || author_matches(&author_email, pattern)
+            }) {
+                info!("skipping {rev}, author matches {pattern}");
+                continue;
+            }
+            let id = commit.id();
+            let commit = Commit {
+                id: id.to_string(),
+                author_email,
+                author_name,
+                committer_email,
+                committer_name,
+                co_authors: trailers::co_authors(&message_trailers),
+                trailers: trailers::to_map(&message_trailers),
+                message,
+                author_date: format_git_time(commit.author().when()),
+                committer_date: format_git_time(commit.committer().when()),
+                files: vec![],
+                insertions: 0,
+                deletions: 0,
+                files_changed: 0,
+                parent_ids: commit.parent_ids().map(|id| id.to_string()).collect(),
+                is_merge: commit.parent_count() > 1,
+                url: remote.as_ref().map(|r| r.commit_url(&id.to_string())),
+                touched_packages: vec![],
+                submodules: vec![],
+            };
+            let mut package_files: HashMap<_, Vec<String>> = HashMap::new();
+            for file in &files {
+                let matched: Vec<_> = package_dirs
+                    .iter()
+                    .filter(|(_, dirs)| dirs.iter().any(|dir| Utf8Path::new(file).starts_with(dir)))
+                    .map(|(pkg, _)| pkg.id())
+                    .collect();
+                for id in &matched {
+                    // Files of nested package are only attributed to it, its changelog
+                    // will be merged into outer package later
+                    if nested_pairs
+                        .iter()
+                        .any(|(outer, inner)| outer == id && matched.contains(inner))
+                    {
+                        continue;
+                    }
+                    package_files.entry(*id).or_default().push(file.clone());
+                }
+            }
+            let mut touched_packages: Vec<_> = package_dirs
+                .iter()
+                .filter(|(pkg, _)| package_files.contains_key(pkg.id()))
+                .map(|(pkg, _)| pkg.name().to_owned())
+                .collect();
+            touched_packages.sort();
+            let mut attributed = vec![];
+            for (pkg, dirs) in &package_dirs {
+                let files = match package_files.remove(pkg.id()) {
+                    Some(files) => files,
+                    None => continue,
+                };
+                if let Some(baseline) = package_baselines.get(pkg.id()) {
+                    if !baseline_ranges[baseline].contains(&rev) {
+                        continue;
+                    }
+                }
+                // Release commit (i.e created by --commit) bumps version of package
+                // itself, and adds changelog entry for it, neither is a change
+                let manifest = dirs[0].join("Cargo.toml");
+                let changelog = dirs[0].join(
+                    statuses[pkg.id()]
+                        .config
+                        .changelog
+                        .as_deref()
+                        .unwrap_or(opts.changelog_name.as_path()),
+                );
+                let lockfile = workspace_dir.join("Cargo.lock");
+                let is_version_bump = |f: &String| f == manifest.as_str() && version_only_files[f];
+                if files.iter().any(is_version_bump)
+                    && files.iter().all(|f| {
+                        is_version_bump(f) || f == changelog.as_str() || f == lockfile.as_str()
+                    })
+                {
+                    continue;
+                }
+                // Depth of the most specific package directory, commit is attributed to
+                let depth = files
+                    .iter()
+                    .flat_map(|f| {
+                        dirs.iter()
+                            .filter(move |dir| Utf8Path::new(f).starts_with(dir))
+                            .map(|dir| dir.components().count())
+                    })
+                    .max()
+                    .unwrap_or_default();
+                let stats: Vec<_> = files.iter().filter_map(|f| file_stats.get(f)).collect();
+                attributed.push((
+                    pkg.id(),
+                    depth,
+                    Commit {
+                        insertions: stats.iter().map(|(i, _)| i).sum(),
+                        deletions: stats.iter().map(|(_, d)| d).sum(),
+                        files_changed: stats.len(),
+                        touched_packages: touched_packages.clone(),
+                        submodules: submodules
+                            .iter()
+                            .filter(|s| files.contains(&s.path))
+                            .cloned()
+                            .collect(),
+                        files,
+                        ..commit.clone()
+                    },
+                ));
+            }
+            if opts.commit_attribution == CommitAttribution::Primary {
+                // Deepest directory wins, then the most changed files, then the first package
+                let primary = attributed
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(idx, (_, depth, commit))| {
+                        (*depth, commit.files.len(), Reverse(*idx))
+                    })
+                    .map(|(idx, _)| idx);
+                if let Some(primary) = primary {
+                    let primary = attributed.swap_remove(primary);
+                    attributed = vec![primary];
+                }
+            }
+            for (id, _, commit) in attributed {
+                package_commits.entry(id).or_default().push(commit);
             }
         }
+        if let Some(path) = &opts.cache {
+            let commits = package_commits
+                .iter()
+                .map(|(id, commits)| (id.repr().to_owned(), commits.clone()))
+                .collect();
+            cache::store(path, &cache_key, commits, truncated)?;
+        }
+    }
 
-        let verdict = (gen.commit_handler)(s.clone(), commits)
-            .map_err(|e| anyhow!("{}", s.stringify_err(&e)))?;
+    // Stdin can only be read once, so code is shared by all evaluators
+    let source = match (&opts.generator, &opts.generator_inline) {
+        (Some(path), _) if path.as_os_str() == "-" => {
+            let mut code = String::new();
+            io::Read::read_to_string(&mut io::stdin(), &mut code)
+                .context("failed to read generator from stdin")?;
+            Some(generator::Source::Inline(code))
+        }
+        (Some(path), _) => Some(generator::Source::File(path.clone())),
+        (None, Some(code)) => Some(generator::Source::Inline(code.clone())),
+        (None, None) => None,
+    };
+    let new_evaluator = || {
+        source
+            .as_ref()
+            .map(|source| Evaluator::new(source, &opts.jpath, &opts.ext_str, &opts.ext_code))
+            .transpose()
+    };
+    // Evaluator of main thread, also fails early on generator errors
+    let evaluator = new_evaluator()?;
 
-        let mut pkg_status = statuses.get_mut(pkg.id()).expect("there is all packages");
-        pkg_status.changelog = verdict.changelog.clone();
-        pkg_status.bump = Bump::from_raw(verdict.bump);
+    let jobs: Vec<_> = package_dirs
+        .iter()
+        .filter(|(pkg, _)| statuses[pkg.id()].publish || opts.propagate_internal)
+        .map(|(pkg, _)| {
+            let commits = package_commits.remove(pkg.id()).unwrap_or_default();
+            (*pkg, statuses[pkg.id()].publish, commits)
+        })
+        .collect();
+    // Zero makes rayon use number of CPUs
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(opts.jobs.unwrap_or(0))
+        .build()?;
+    thread_local! {
+        // `map_init` would create evaluator for every split of jobs, while it is
+        // only needed once per worker thread, which lives as long as the pool
+        static EVALUATOR: RefCell<Option<Result<Option<Evaluator>>>> = RefCell::new(None);
+    }
+    let processed: Vec<Result<_>> = pool.install(|| {
+        jobs.into_par_iter()
+            .map(|(pkg, publish, commits)| {
+                EVALUATOR.with(|evaluator| -> Result<_> {
+                    let mut evaluator = evaluator.borrow_mut();
+                    let evaluator = evaluator
+                        .get_or_insert_with(&new_evaluator)
+                        .as_ref()
+                        .map_err(|e| anyhow!("{e:#}"))?;
+                    let baseline = package_baselines.get(pkg.id()).copied().or(hide);
+                    let processed = process_package(
+                        &opts,
+                        evaluator.as_ref(),
+                        &remote,
+                        pkg,
+                        publish,
+                        baseline,
+                        commits,
+                    )?;
+                    Ok((pkg, processed))
+                })
+            })
+            .collect()
+    });
+
+    // Packages with commits, but without changelog, for --require-changelog
+    let mut missing_changelog = vec![];
+    for result in processed {
+        let (pkg, processed) = result?;
+        if opts.require_changelog
+            && !processed.subjects.is_empty()
+            && processed.changelog.trim().is_empty()
+        {
+            warn!(
+                "{} has changes, but generator produced no changelog for them:\n{}",
+                pkg.name(),
+                processed
+                    .subjects
+                    .iter()
+                    .map(|s| format!("- {s}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            missing_changelog.push(pkg.name());
+        }
+        let pkg_status = statuses.get_mut(pkg.id()).expect("there is all packages");
+        if !opts.no_changelog {
+            pkg_status.changelog = processed.changelog;
+            pkg_status.entries = processed.entries;
+        }
+        pkg_status.bump = processed.bump;
         if pkg_status.bump > Bump::None {
             pkg_status.bump_reasons.push(format!(
                 "changelog generator decided to bump to {:?}",
                 pkg_status.bump
             ));
         }
+        if let Some(version) = processed.version {
+            pkg_status.bump_reasons.push(format!(
+                "changelog generator decided to set version to {version}"
+            ));
+            pkg_status.version = Some(version);
+        }
+        if processed.breaking {
+            pkg_status.bump = Bump::Major;
+            pkg_status
+                .bump_reasons
+                .push("cargo-semver-checks detected breaking API change".to_owned());
+        }
+    }
+
+    // Forced bumps are floors, which are propagated as usual
+    for (name, bump) in &opts.force_bump {
+        let status = statuses
+            .values_mut()
+            .find(|status| status.package.name() == name)
+            .expect("--force-bump packages are validated");
+        if status.bump < *bump {
+            status.bump = *bump;
+        }
+        status.add_bump_reason(format!("{bump:?} bump forced by CLI"));
+    }
+
+    // Changelog of nested package is appended to the top-level package one,
+    // under heading with nested package name
+    let mut merged: Vec<_> = nested_pairs
+        .iter()
+        .filter(|(outer, _)| !nested.contains(outer))
+        .collect();
+    merged.sort_by_key(|(_, inner)| statuses[inner].package.name());
+    for (outer, inner) in merged {
+        let inner = statuses.get_mut(inner).expect("there is all packages");
+        let inner_changelog = mem::take(&mut inner.changelog);
+        if inner_changelog.trim() == "" {
+            continue;
+        }
+        let name = inner.package.name();
+        let outer = statuses.get_mut(outer).expect("there is all packages");
+        write!(outer.changelog, "\n\n## {name}\n\n")?;
+        outer
+            .changelog
+            .push_str(&changelog::demote_headings(inner_changelog.trim()));
     }
 
     let mut bumped = true;
@@ -247,150 +1924,656 @@ fn main() -> Result<()> {
             for (a, b) in [(outer, inner), (inner, outer)] {
                 if statuses[b].bump < statuses[a].bump {
                     let bump = statuses[a].bump;
-                    let mut a = statuses.get_mut(inner).expect("there is all packages");
-                    a.bump_reasons
-                        .push("nested packages should have equal bump".to_string());
-                    a.bump = bump;
+                    let a_name = statuses[a].package.name();
+                    let b = statuses.get_mut(b).expect("there is all packages");
+                    let b_name = b.package.name();
+                    b.add_bump_reason(format!(
+                        "{b_name} is nested with {a_name}, bumped to match its {bump:?} bump"
+                    ));
+                    b.bump = bump;
                     bumped = true;
                 }
             }
         }
+        if equalize_inherited(&mut statuses, &inheriting) {
+            bumped = true;
+        }
+        // Nested packages and packages sharing version are still equalized
+        if opts.no_propagate {
+            continue;
+        }
         for id in workspace.package_ids(DependencyDirection::Forward) {
-            if statuses[id].bump == Bump::None {
+            let propagated = opts.dependency_bump.propagated(statuses[id].bump);
+            if propagated == Bump::None {
                 continue;
             }
             for dependent in workspace.package_ids(DependencyDirection::Forward) {
-                if !metadata.directly_depends_on(dependent, id)? {
+                if !propagates_to(&opts, metadata.metadata(dependent)?, id) {
                     continue;
                 }
-                let old_bump = statuses[dependent].bump;
-                if old_bump >= Bump::Patch {
+                let dependent = statuses.get_mut(dependent).expect("there is all packages");
+                if !dependent.publish && !opts.propagate_internal {
                     continue;
                 }
-                let mut dependent = statuses.get_mut(dependent).expect("there is all packages");
-                dependent
-                    .bump_reasons
-                    .push(format!("dependency ({id}) had bump",));
-                dependent.bump = Bump::Patch;
-                bumped = true;
-            }
-        }
-    }
-
-    if !opts.execute {
-        // TODO: move result message generation to generator
-        let mut out = String::new();
-        write!(
-            out,
-            "Hey, seems like you need to have changelog and version bumps for your PR?\n\nDon't worry, i've got you covered, if you have proper commit messages, then changelog generated by me should be okay for you\n\n"
-        )?;
-
-        write!(out, "# Changes\n\n")?;
-        write!(
-            out,
-            "After your confirmation, I will append the following entries to changelogs of packages:\n\n"
-        )?;
-        for package in statuses.values() {
-            if package.changelog.trim() == "" {
-                continue;
+                // Reason is recorded even if it doesn't raise bump level any further
+                dependent.add_bump_reason(format!("dependency ({id}) had bump"));
+                if dependent.bump < propagated {
+                    dependent.bump = propagated;
+                    bumped = true;
+                }
             }
-            write!(
-                out,
-                "## {} v{} ({:?} bump)\n\n",
-                package.package.name(),
-                package.final_version(),
-                package.bump
-            )?;
-            for line in package.changelog.trim().lines() {
-                if line.starts_with('#') {
-                    write!(out, "#")?;
+        }
+    }
+
+    let release_date = release_date(&opts, &repo, until)?;
+    let policy = BumpPolicy {
+        promote_major: opts.promote_major,
+        prerelease: opts.prerelease_policy,
+        build_metadata: opts.build_metadata.clone(),
+    };
+    for package in statuses.values_mut() {
+        let name = package.package.name();
+        let baseline = if package.publish && package.is_bumped() {
+            opts.baseline
+        } else {
+            Baseline::Manifest
+        };
+        match baseline {
+            Baseline::Manifest => {
+                let rev = package_baselines
+                    .get(package.package.id())
+                    .copied()
+                    .or(hide);
+                let bumped = package.bump > Bump::None && package.version.is_none();
+                if let (Some(rev), true) = (rev, bumped) {
+                    let released = manifest::version_at(
+                        &repo,
+                        rev,
+                        &pkgdirs[package.package.id()].join("Cargo.toml"),
+                        &workspace_dir.join("Cargo.toml"),
+                    )?;
+                    let manifest = package.package.version();
+                    match released {
+                        Some(released)
+                            if is_bumped_since(
+                                &released,
+                                manifest,
+                                package.bump,
+                                &policy,
+                                &opts.versioning,
+                                release_date.date_naive(),
+                            ) =>
+                        {
+                            info!(
+                                "{name} manifest is already bumped from {released} to {manifest}"
+                            );
+                            package.add_bump_reason(format!(
+                                "manifest already has version {manifest}, \
+                                bumped from {released} since last release"
+                            ));
+                            package.base_version = released;
+                        }
+                        _ => {}
+                    }
                 }
-                writeln!(out, "{}", line)?;
-            }
-        }
-        write!(out, "\n\n")?;
-        write!(out, "# Bumps\n\n")?;
-        // TODO: We only have at most one bump reason per bump level, but there may be multiple
-        write!(
-            out,
-            "I may not be able to describe reason for bump, but they should be required:\n\n"
-        )?;
-        for package in statuses.values() {
-            if package.bump == Bump::None {
-                continue;
             }
-            write!(
-                out,
-                "{} `{}` -> `{}`\n\n",
-                package.package.name(),
-                package.package.version(),
-                package.bump.apply(package.package.version())
+            Baseline::CratesIo => match registry::latest_version(name)? {
+                Some(version) => {
+                    if &version != package.package.version() {
+                        info!(
+                            "{name} is published as {version}, but manifest has version {}",
+                            package.package.version()
+                        );
+                    }
+                    package.base_version = version;
+                }
+                None => info!("{name} is not published, using manifest version as baseline"),
+            },
+            Baseline::GitTag => {
+                let prefix = package.tag_prefix(&opts.tag_prefix);
+                match tags::latest_tag(&repo, &format!("{prefix}*"), until)? {
+                    Some((version, _)) => {
+                        info!("using {prefix}{version} tag as baseline of {name}");
+                        package.base_version = version;
+                    }
+                    None if opts.allow_untagged => {
+                        info!("{name} has no {prefix}* tags, using manifest version as baseline")
+                    }
+                    None => {
+                        return Err(anyhow!(
+                            "{name} has no {prefix}* tags reachable from {until}, \
+                            tag its last release or pass --allow-untagged"
+                        ))
+                    }
+                }
+            }
+        }
+        package.resolve_final_version(&policy, &opts.versioning, release_date.date_naive());
+    }
+
+    // Sorted to make both plan and file writes order predictable
+    let mut packages: Vec<_> = statuses.into_values().collect();
+    packages.sort_by(|a, b| a.package.name().cmp(b.package.name()));
+    // Internal and not selected packages are never released, however their
+    // dependency requirements are still kept up to date
+    let (packages, internal): (Vec<_>, Vec<_>) = packages
+        .into_iter()
+        .partition(|package| package.publish && opts.is_selected(package.package.name()));
+
+    // Flags are mutually exclusive, dry-run is the default
+    if opts.dry_run || !opts.execute {
+        warn_released(&opts, &packages)?;
+        let out = match opts.format {
+            Format::Markdown => match evaluator
+                .as_ref()
+                .and_then(|e| Some((e, e.generator.plan_handler.as_ref()?)))
+            {
+                Some((evaluator, plan_handler)) => {
+                    plan_handler(evaluator.state.clone(), plan::generator_input(&packages))
+                        .map_err(|e| evaluator.error("planHandler", &e))?
+                }
+                None => plan::markdown(&packages, truncated, opts.no_changelog)?,
+            },
+            Format::Json => plan::json(&packages, truncated)?,
+        };
+        if opts.github_comment {
+            if !matches!(opts.format, Format::Markdown) {
+                return Err(anyhow!("--github-comment requires markdown plan format"));
+            }
+            let pr = opts.pr.or_else(github::pr_from_env).ok_or_else(|| {
+                anyhow!(
+                    "pull request number is not specified, and GITHUB_REF is not a pull request"
+                )
+            })?;
+            github::upsert_comment(pr, &out)?;
+        }
+        if !opts.no_step_summary {
+            if let Some(path) = env::var_os("GITHUB_STEP_SUMMARY") {
+                let summary = match opts.format {
+                    Format::Markdown => format!("{out}\n"),
+                    Format::Json => format!("```json\n{out}\n```\n"),
+                };
+                fs::OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(&path)
+                    .and_then(|mut file| io::Write::write_all(&mut file, summary.as_bytes()))
+                    .context("failed to write GITHUB_STEP_SUMMARY")?;
+            }
+        }
+        match &opts.output {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(path, out).with_context(|| format!("failed to write {path}"))?;
+            }
+            None => println!("{out}"),
+        }
+        if opts.show_diff || opts.emit_patches.is_some() {
+            let edits = release_edits(
+                &opts,
+                &repo,
+                until,
+                &remote,
+                metadata.workspace().root(),
+                &packages,
+                &internal,
             )?;
-            for reason in &package.bump_reasons {
-                write!(out, "- {}\n\n", reason)?;
+            let root = repo_root(&repo)?;
+            if opts.show_diff {
+                for edit in edits.iter() {
+                    print!("{}", edit.diff(&root)?);
+                }
+            }
+            if let Some(dir) = &opts.emit_patches {
+                edit::emit_patches(&edits, &root, dir)?;
             }
         }
-        println!("{out}");
+        if opts.check && !missing_changelog.is_empty() {
+            eprintln!(
+                "packages have changes without changelog: {}",
+                missing_changelog.join(", ")
+            );
+            process::exit(1);
+        }
+        if opts.check
+            && packages
+                .iter()
+                .any(|package| package.is_bumped() || package.changelog.trim() != "")
+        {
+            process::exit(1);
+        }
         return Ok(());
     }
 
-    for package in statuses.values() {
-        if package.changelog.is_empty() {
-            continue;
+    if !opts.allow_dirty {
+        let written: Vec<_> = opts.cache.iter().chain(&opts.output).collect();
+        let dirty = dirty_files(&repo, &written)?;
+        if !dirty.is_empty() {
+            return Err(anyhow!(
+                "working tree has uncommitted changes, commit them or pass --allow-dirty:\n{}",
+                dirty.join("\n")
+            ));
+        }
+    }
+
+    let edits = release_edits(
+        &opts,
+        &repo,
+        until,
+        &remote,
+        metadata.workspace().root(),
+        &packages,
+        &internal,
+    )?;
+    if let Some(branch) = &opts.branch {
+        release::switch_branch(&repo, branch, opts.force_branch)?;
+        info!("switched to branch {branch}");
+    }
+    // Files to be committed with --commit
+    let mut written = vec![];
+    for edit in edits.iter() {
+        edit.write()?;
+        written.push(edit.path.clone());
+    }
+
+    if opts.commit && edits.is_empty() {
+        info!("nothing was changed, not creating release commit");
+    } else if opts.commit {
+        let id = release::commit(&repo, &written, &opts.commit_message)?;
+        info!("created release commit {id}");
+        if opts.tag {
+            for package in packages.iter().filter(|p| p.is_bumped()) {
+                release::tag(
+                    &repo,
+                    id,
+                    &package.tag_prefix(&opts.tag_prefix),
+                    package.package.name(),
+                    &package.final_version,
+                )?;
+            }
         }
-        let mut changelog_path = package.package.manifest_path().to_path_buf();
-        changelog_path.pop();
-        changelog_path.push("CHANGELOG.md");
+    }
 
-        // TODO: check if error is not ENOENT
-        let old_changelog = fs::read_to_string(&changelog_path).unwrap_or_default();
-        let mut new_changelog = String::new();
+    Ok(())
+}
 
-        let next_start = if let Some(offset) = old_changelog.find(COMMENT_START) {
-            new_changelog.push_str(&old_changelog[..offset + COMMENT_START.len()]);
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
 
-            offset + COMMENT_START.len()
-        } else {
-            new_changelog.push_str(COMMENT_START);
-            0
-        };
-        let next = &old_changelog[next_start..];
+    use guppy::graph::PackageGraph;
+    use serde_json::json;
 
-        let date = Utc::now().date().format("%Y-%m-%d").to_string();
-        write!(
-            new_changelog,
-            "## [v{}] {}\n\n",
-            package.final_version(),
-            date
-        )?;
-        for line in package.changelog.trim().lines() {
-            if line.starts_with('#') {
-                write!(new_changelog, "#")?;
-            }
-            writeln!(new_changelog, "{}", line)?;
+    use super::*;
+
+    fn opts(args: &[&str]) -> Opts {
+        let base = ["bureaucrate", "--root", "--preset", "conventional"];
+        Opts::try_parse_from(base.iter().chain(args)).unwrap()
+    }
+
+    /// Graph of workspace at `root`, members are listed with their dependencies
+    /// on other members and kinds of them (`None` for normal, `dev` or `build`)
+    fn graph(root: &Utf8Path, members: &[(&str, &[(&str, Option<&str>)])]) -> PackageGraph {
+        let id = |name: &str| format!("{name} 1.0.0 (path+file://{root}/{name})");
+        let packages: Vec<_> = members
+            .iter()
+            .map(|(name, deps)| {
+                let dependencies: Vec<_> = deps
+                    .iter()
+                    .map(|(dep, kind)| {
+                        json!({
+                            "name": dep,
+                            "source": null,
+                            "req": "^1.0.0",
+                            "kind": kind,
+                            "rename": null,
+                            "optional": false,
+                            "uses_default_features": true,
+                            "features": [],
+                            "target": null,
+                            "registry": null,
+                            "path": format!("{root}/{dep}"),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "name": name,
+                    "version": "1.0.0",
+                    "id": id(name),
+                    "license": null,
+                    "license_file": null,
+                    "description": null,
+                    "source": null,
+                    "dependencies": dependencies,
+                    "targets": [{
+                        "kind": ["lib"],
+                        "crate_types": ["lib"],
+                        "name": name,
+                        "src_path": format!("{root}/{name}/src/lib.rs"),
+                        "edition": "2021",
+                        "doc": true,
+                        "doctest": true,
+                        "test": true,
+                    }],
+                    "features": {},
+                    "manifest_path": format!("{root}/{name}/Cargo.toml"),
+                    "metadata": null,
+                    "publish": null,
+                    "authors": [],
+                    "categories": [],
+                    "keywords": [],
+                    "readme": null,
+                    "repository": null,
+                    "homepage": null,
+                    "documentation": null,
+                    "edition": "2021",
+                    "links": null,
+                    "default_run": null,
+                    "rust_version": null,
+                })
+            })
+            .collect();
+        let nodes: Vec<_> = members
+            .iter()
+            .map(|(name, deps)| {
+                let pkg_deps: Vec<_> = deps
+                    .iter()
+                    .map(|(dep, kind)| {
+                        json!({
+                            "name": dep,
+                            "pkg": id(dep),
+                            "dep_kinds": [{ "kind": kind, "target": null }],
+                        })
+                    })
+                    .collect();
+                json!({
+                    "id": id(name),
+                    "dependencies": deps.iter().map(|(dep, _)| id(dep)).collect::<Vec<_>>(),
+                    "deps": pkg_deps,
+                    "features": [],
+                })
+            })
+            .collect();
+        let metadata = json!({
+            "packages": packages,
+            "workspace_members": members.iter().map(|(name, _)| id(name)).collect::<Vec<_>>(),
+            "resolve": { "nodes": nodes, "root": null },
+            "target_directory": format!("{root}/target"),
+            "version": 1,
+            "workspace_root": root,
+            "metadata": null,
+        });
+        PackageGraph::from_json(metadata.to_string()).unwrap()
+    }
+
+    fn status<'g>(graph: &'g PackageGraph, name: &str) -> PackageStatus<'g> {
+        let package = graph
+            .workspace()
+            .member_by_path(name)
+            .expect("package is a workspace member");
+        PackageStatus {
+            changelog: "### Fixed\n\n- Everything\n".to_owned(),
+            entries: vec![],
+            bump: Bump::Patch,
+            bump_reasons: vec![],
+            version: None,
+            base_version: package.version().clone(),
+            final_version: Version::new(1, 0, 1),
+            publish: true,
+            config: PackageConfig::default(),
+            package,
         }
-        new_changelog.push('\n');
-        new_changelog.push_str(next);
+    }
 
-        fs::write(&changelog_path, new_changelog.trim())?;
+    #[test]
+    fn dev_dependents_are_not_bumped_by_default() {
+        let graph = graph(
+            Utf8Path::new("/workspace"),
+            &[
+                ("a", &[]),
+                ("b", &[("a", Some("dev"))]),
+                ("c", &[("a", None)]),
+                ("d", &[("a", Some("build"))]),
+            ],
+        );
+        let member = |name: &str| graph.workspace().member_by_path(name).unwrap();
+        let a = member("a").id();
+        let propagated = |opts: &Opts| {
+            ["b", "c", "d"]
+                .into_iter()
+                .filter(|name| propagates_to(opts, member(*name), a))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(propagated(&opts(&[])), ["c", "d"]);
+        assert_eq!(
+            propagated(&opts(&["--propagate-dev-deps"])),
+            ["b", "c", "d"]
+        );
+        assert!(!propagates_to(&opts(&[]), member("a"), member("c").id()));
     }
-    for (_, package) in statuses {
-        let manifest_path = package.package.manifest_path();
-        let manifest = fs::read_to_string(&manifest_path)?;
-        let mut manifest: toml_edit::Document = manifest.parse()?;
-        let root_table = manifest.as_table_mut();
 
-        let package_table = root_table
-            .get_mut("package")
-            .expect("cargo metadata is fine")
-            .as_table_like_mut()
-            .expect("metadata is fine");
-        package_table.insert(
-            "version",
-            toml_edit::value(package.final_version().to_string()),
+    #[test]
+    fn version_only_manifest_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let tree = |manifest: &str| {
+            let blob = repo.blob(manifest.as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("Cargo.toml", blob, 0o100644).unwrap();
+            repo.find_tree(builder.write().unwrap()).unwrap()
+        };
+        let version_only = |old: &str, new: &str| {
+            let diff = repo
+                .diff_tree_to_tree(Some(&tree(old)), Some(&tree(new)), None)
+                .unwrap();
+            let delta = diff.deltas().next().expect("manifest is changed");
+            is_version_only(&repo, &delta).unwrap()
+        };
+        let manifest = |version: &str, dependency: &str| {
+            format!(
+                "[package]\nname = \"a\"\nversion = \"{version}\"\n\n\
+                [dependencies.b]\nversion = \"{dependency}\"\npath = \"../b\"\n"
+            )
+        };
+        assert!(version_only(
+            &manifest("1.0.0", "1.0.0"),
+            &manifest("1.1.0", "1.0.0")
+        ));
+        // Dependency requirement is changed too
+        assert!(!version_only(
+            &manifest("1.0.0", "1.0.0"),
+            &manifest("1.1.0", "1.1.0")
+        ));
+        assert!(!version_only(
+            &manifest("1.0.0", "1.0.0"),
+            &manifest("1.0.0", "1.1.0")
+        ));
+        // Version of workspace-inheriting packages
+        assert!(!version_only(
+            "[workspace.package]\nversion = \"1.0.0\"\n",
+            "[workspace.package]\nversion = \"1.1.0\"\n"
+        ));
+    }
+
+    #[test]
+    fn release_date_near_midnight() {
+        let time = DateTime::parse_from_rfc3339("2024-06-01T23:30:00Z").unwrap();
+        let date = |time, timezone: &str| {
+            let timezone: Timezone = timezone.parse().unwrap();
+            timezone.convert(time).date_naive().to_string()
+        };
+        assert_eq!(date(time, "utc"), "2024-06-01");
+        assert_eq!(date(time, "+05:00"), "2024-06-02");
+        assert_eq!(date(time, "-05:00"), "2024-06-01");
+        let time = DateTime::parse_from_rfc3339("2024-06-02T00:30:00Z").unwrap();
+        assert_eq!(date(time, "utc"), "2024-06-02");
+        assert_eq!(date(time, "+05:00"), "2024-06-02");
+        assert_eq!(date(time, "-05:00"), "2024-06-01");
+        // Commit time in other timezone is still the same instant
+        let time = DateTime::parse_from_rfc3339("2024-06-02T01:30:00+02:00").unwrap();
+        assert_eq!(
+            Timezone::from_str("-05:00")
+                .unwrap()
+                .convert(time)
+                .to_rfc3339(),
+            "2024-06-01T18:30:00-05:00"
         );
-        fs::write(&manifest_path, manifest.to_string())?;
+        assert!("05:00".parse::<Timezone>().is_err());
+        assert!("+25:00".parse::<Timezone>().is_err());
     }
 
-    Ok(())
+    #[test]
+    fn inherited_version_bumps_are_equalized() {
+        let graph = graph(
+            Utf8Path::new("/workspace"),
+            &[("a", &[]), ("b", &[]), ("c", &[])],
+        );
+        let mut statuses = HashMap::new();
+        for (name, bump) in [("a", Bump::Patch), ("b", Bump::Minor), ("c", Bump::None)] {
+            let mut status = status(&graph, name);
+            status.bump = bump;
+            statuses.insert(status.package.id(), status);
+        }
+        let id = |name: &str| graph.workspace().member_by_path(name).unwrap().id();
+        let inheriting = [id("a"), id("b")];
+
+        assert!(equalize_inherited(&mut statuses, &inheriting));
+        assert_eq!(statuses[id("a")].bump, Bump::Minor);
+        assert_eq!(
+            statuses[id("a")].bump_reasons,
+            ["a inherits workspace version, bumped to match Minor bump of b"]
+        );
+        assert_eq!(statuses[id("b")].bump, Bump::Minor);
+        assert_eq!(statuses[id("c")].bump, Bump::None);
+        assert!(!equalize_inherited(&mut statuses, &inheriting));
+    }
+
+    #[test]
+    fn plan_without_changelog() {
+        let graph = graph(Utf8Path::new("/workspace"), &[("a", &[])]);
+        let mut package = status(&graph, "a");
+        let plan = plan::markdown(&[package], false, false).unwrap();
+        assert!(plan.contains("# Changes\n\n"));
+        assert!(plan.contains("I will append the following entries"));
+
+        package = status(&graph, "a");
+        // Changelog is dropped with --no-changelog, while bump is kept
+        package.changelog.clear();
+        let plan = plan::markdown(&[package], false, true).unwrap();
+        assert!(!plan.contains("# Changes"));
+        assert!(!plan.contains("I will append"));
+        assert!(plan.contains("# Bumps\n\n"));
+        assert!(plan.contains("a `1.0.0` -> `1.0.1`"));
+    }
+
+    #[test]
+    fn dry_run_files_are_not_dirty() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_owned()).unwrap();
+        let repo = Repository::init(&root).unwrap();
+        for name in ["cache.json", "plan.md", "notes.txt"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        let written = [
+            root.join("cache.json"),
+            root.join("plan.md"),
+            root.join("missing.md"),
+        ];
+        let written: Vec<_> = written.iter().collect();
+        assert_eq!(dirty_files(&repo, &written).unwrap(), ["notes.txt"]);
+        assert_eq!(dirty_files(&repo, &[]).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn missing_changelog_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("CHANGELOG.md")).unwrap();
+        assert_eq!(read_changelog(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn unreadable_changelog_aborts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_owned()).unwrap();
+        let graph = graph(&root, &[("a", &[])]);
+        let package = status(&graph, "a");
+        let path = root.join("a/CHANGELOG.md");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(&path, "## [v1.0.0] 2022-01-01\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions are not checked for root
+        if fs::read(&path).is_ok() {
+            fs::remove_file(&path).unwrap();
+            // Reading directory fails regardless of privileges
+            fs::create_dir(&path).unwrap();
+        }
+
+        let error = read_changelog(&path).unwrap_err();
+        assert!(format!("{error:#}").contains("failed to read"));
+
+        let repo = Repository::init(&root).unwrap();
+        let error = release_edits(
+            &opts(&["--execute"]),
+            &repo,
+            Oid::zero(),
+            &None,
+            &root,
+            &[package],
+            &[],
+        )
+        .err()
+        .expect("unreadable changelog is not overwritten");
+        assert!(format!("{error:#}").contains(path.as_str()));
+    }
+
+    #[test]
+    fn retried_release_is_not_bumped_again() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let policy = BumpPolicy::default();
+        let bumped_since = |released: &str, manifest: &str, bump| {
+            is_bumped_since(
+                &Version::parse(released).unwrap(),
+                &Version::parse(manifest).unwrap(),
+                bump,
+                &policy,
+                &Versioning::Semver,
+                date,
+            )
+        };
+        // Manifest was bumped by previous --execute run
+        assert!(bumped_since("1.0.0", "1.1.0", Bump::Minor));
+        assert!(bumped_since("1.0.0", "1.0.1", Bump::Patch));
+        // Manifest was edited by hand, it is bumped as usual
+        assert!(!bumped_since("1.0.0", "1.1.0", Bump::Patch));
+        assert!(!bumped_since("1.0.0", "1.1.0", Bump::Major));
+        assert!(!bumped_since("1.0.0", "1.0.0", Bump::Patch));
+        // Dev version is not a release
+        assert!(!bumped_since("1.2.0", "1.3.0-dev", Bump::Patch));
+        let increment = BumpPolicy {
+            prerelease: PrereleasePolicy::Increment,
+            ..BumpPolicy::default()
+        };
+        assert!(!is_bumped_since(
+            &Version::parse("1.0.0-rc.1").unwrap(),
+            &Version::parse("1.0.0-rc.2").unwrap(),
+            Bump::Patch,
+            &increment,
+            &Versioning::Semver,
+            date,
+        ));
+    }
+
+    #[test]
+    fn manifest_ahead_of_baseline_is_not_kept() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let graph = graph(Utf8Path::new("/workspace"), &[("a", &[])]);
+        let mut package = status(&graph, "a");
+        // Tag or crates.io baseline is behind manifest version 1.0.0
+        package.base_version = Version::parse("0.9.0").unwrap();
+        package.resolve_final_version(&BumpPolicy::default(), &Versioning::Semver, date);
+        assert_eq!(package.final_version, Version::new(0, 9, 1));
+        assert!(package.bump_reasons.is_empty());
+    }
 }
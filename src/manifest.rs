@@ -0,0 +1,535 @@
+//! `Cargo.toml` modification
+
+use std::{collections::HashMap, fs};
+
+use anyhow::{anyhow, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use git2::{ErrorCode, Oid, Repository};
+use semver::{Op, Version, VersionReq};
+use toml_edit::{Document, Item, TableLike};
+use tracing::warn;
+
+use crate::edit::{Edit, Edits};
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+pub struct Manifest {
+    path: Utf8PathBuf,
+    original: String,
+    document: Document,
+}
+impl Manifest {
+    /// Open manifest with pending `edits` applied
+    pub fn open(path: &Utf8Path, edits: &Edits) -> Result<Self> {
+        let original = match edits.current(path) {
+            Some(text) => text.to_owned(),
+            None => fs::read_to_string(path)?,
+        };
+        let document = original.parse()?;
+        Ok(Self {
+            path: path.to_owned(),
+            original,
+            document,
+        })
+    }
+    /// Edit of manifest, if it was modified
+    pub fn edit(&self) -> Option<Edit> {
+        let updated = self.document.to_string();
+        if updated == self.original {
+            return None;
+        }
+        Some(Edit {
+            path: self.path.clone(),
+            old: Some(self.original.clone()),
+            new: updated,
+        })
+    }
+
+    fn package_table(&self) -> Option<&dyn TableLike> {
+        self.document.get("package")?.as_table_like()
+    }
+
+    /// Is version specified as `version.workspace = true`, false for virtual manifests
+    pub fn inherits_version(&self) -> bool {
+        self.package_table()
+            .and_then(|t| t.get("version"))
+            .and_then(Item::as_table_like)
+            .and_then(|t| t.get("workspace"))
+            .and_then(Item::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// Set `[package].version`, virtual manifests have no `[package]` table,
+    /// their version is set by [`Self::set_workspace_version`] instead
+    pub fn set_version(&mut self, version: &Version) -> Result<()> {
+        let package_table = self
+            .document
+            .get_mut("package")
+            .and_then(Item::as_table_like_mut)
+            .ok_or_else(|| anyhow!("{} has no [package] table", self.path))?;
+        replace_version(package_table, version);
+        Ok(())
+    }
+
+    /// Set `[workspace.package].version`, which is inherited by packages
+    pub fn set_workspace_version(&mut self, version: &Version) -> Result<()> {
+        let package_table = self
+            .document
+            .get_mut("workspace")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|t| t.get_mut("package"))
+            .and_then(Item::as_table_like_mut)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} has no [workspace.package] table, but version is inherited from it",
+                    self.path
+                )
+            })?;
+        replace_version(package_table, version);
+        Ok(())
+    }
+
+    /// Update requirements in `[workspace.dependencies]`, which are inherited by
+    /// members with `dep.workspace = true`
+    pub fn update_workspace_requirements(&mut self, versions: &HashMap<&str, Version>) {
+        if let Some(table) = self
+            .document
+            .get_mut("workspace")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|t| t.get_mut("dependencies"))
+            .and_then(Item::as_table_like_mut)
+        {
+            update_table_requirements(&self.path, table, versions);
+        }
+    }
+
+    /// Update requirements on packages from `versions` (by package name), which
+    /// wouldn't match new versions anymore, in all dependency tables, including
+    /// target-specific ones
+    pub fn update_requirements(&mut self, versions: &HashMap<&str, Version>) {
+        let root = self.document.as_table_mut();
+        for name in DEPENDENCY_TABLES {
+            if let Some(table) = root.get_mut(name).and_then(Item::as_table_like_mut) {
+                update_table_requirements(&self.path, table, versions);
+            }
+        }
+        if let Some(targets) = root.get_mut("target").and_then(Item::as_table_like_mut) {
+            for (_, target) in targets.iter_mut() {
+                let target = match target.as_table_like_mut() {
+                    Some(t) => t,
+                    None => continue,
+                };
+                for name in DEPENDENCY_TABLES {
+                    if let Some(table) = target.get_mut(name).and_then(Item::as_table_like_mut) {
+                        update_table_requirements(&self.path, table, versions);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Set `version` key of table, keeping comments and whitespace around its value.
+/// Manifest is left intact, if it already has this version
+fn replace_version(table: &mut dyn TableLike, version: &Version) {
+    let version = version.to_string();
+    match table.get_mut("version").and_then(Item::as_value_mut) {
+        Some(old) if old.as_str() == Some(version.as_str()) => {}
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = version.into();
+            *old.decor_mut() = decor;
+        }
+        None => {
+            table.insert("version", toml_edit::value(version));
+        }
+    }
+}
+
+/// Package version in `manifest` as of `rev`, inherited one is read from
+/// `root_manifest`. Paths are relative to repository root, `None` if package
+/// didn't exist at `rev`
+pub fn version_at(
+    repo: &Repository,
+    rev: Oid,
+    manifest: &Utf8Path,
+    root_manifest: &Utf8Path,
+) -> Result<Option<Version>> {
+    let tree = repo.find_commit(rev)?.tree()?;
+    let read = |path: &Utf8Path| -> Result<Option<Document>> {
+        let entry = match tree.get_path(path.as_std_path()) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let blob = entry.to_object(repo)?.peel_to_blob()?;
+        let text = std::str::from_utf8(blob.content())
+            .with_context(|| format!("{path} at {rev} is not utf-8"))?;
+        let document = text
+            .parse()
+            .with_context(|| format!("failed to parse {path} at {rev}"))?;
+        Ok(Some(document))
+    };
+    let document = match read(manifest)? {
+        Some(document) => document,
+        None => return Ok(None),
+    };
+    let version = match document.get("package").and_then(|p| p.get("version")) {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+    let version = if version.is_str() {
+        version.as_str().map(ToOwned::to_owned)
+    } else {
+        read(root_manifest)?.and_then(|root| {
+            root.get("workspace")?
+                .get("package")?
+                .get("version")?
+                .as_str()
+                .map(ToOwned::to_owned)
+        })
+    };
+    version
+        .map(|v| {
+            Version::parse(&v).with_context(|| format!("invalid version in {manifest} at {rev}"))
+        })
+        .transpose()
+}
+
+fn update_table_requirements(
+    path: &Utf8Path,
+    table: &mut dyn TableLike,
+    versions: &HashMap<&str, Version>,
+) {
+    for (key, dep) in table.iter_mut() {
+        // Dependency may be renamed
+        let name = dep
+            .as_table_like()
+            .and_then(|t| t.get("package"))
+            .and_then(Item::as_str)
+            .unwrap_or_else(|| key.get())
+            .to_owned();
+        let version = match versions.get(name.as_str()) {
+            Some(v) => v,
+            None => continue,
+        };
+        let req = if dep.is_str() {
+            dep.as_value_mut()
+        } else {
+            dep.as_table_like_mut()
+                .and_then(|t| t.get_mut("version"))
+                .and_then(Item::as_value_mut)
+        };
+        let req = match req {
+            Some(req) => req,
+            // Path-only or workspace-inherited dependency
+            None => continue,
+        };
+        let old = match req.as_str() {
+            Some(old) => old,
+            None => continue,
+        };
+        match bump_requirement(old, version) {
+            Ok(Some(new)) => {
+                let decor = req.decor().clone();
+                *req = new.into();
+                *req.decor_mut() = decor;
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{path}: not updating requirement on {name}: {e}"),
+        }
+    }
+}
+
+/// Rewrite requirement to match `version`, keeping operator and precision
+/// where possible. Returns `None` if requirement already matches
+fn bump_requirement(req: &str, version: &Version) -> Result<Option<String>> {
+    let parsed = VersionReq::parse(req)?;
+    if parsed.matches(version) {
+        return Ok(None);
+    }
+    let comparator = match parsed.comparators.as_slice() {
+        [comparator] => comparator,
+        _ => return Err(anyhow!("can't update multi-comparator requirement {req:?}")),
+    };
+    if !matches!(comparator.op, Op::Exact | Op::Tilde | Op::Caret) {
+        return Err(anyhow!(
+            "can't update requirement {req:?} with range operator"
+        ));
+    }
+    // Keep operator as written, absence of operator means caret
+    let op_len = req
+        .find(|c: char| c.is_ascii_digit())
+        .expect("valid requirement has version");
+    let mut out = req[..op_len].to_owned();
+
+    let with_patch = comparator.patch.is_some() || version.patch != 0 || !version.pre.is_empty();
+    let with_minor = comparator.minor.is_some() || version.minor != 0 || with_patch;
+    out.push_str(&version.major.to_string());
+    if with_minor {
+        out.push_str(&format!(".{}", version.minor));
+    }
+    if with_patch {
+        out.push_str(&format!(".{}", version.patch));
+    }
+    if !version.pre.is_empty() {
+        out.push_str(&format!("-{}", version.pre));
+    }
+    Ok(Some(out))
+}
+
+/// Set versions of workspace packages from `versions` in `Cargo.lock`, including
+/// `name version` references to them, other entries are kept intact.
+/// Returns edit, if lockfile was changed
+pub fn update_lockfile(path: &Utf8Path, versions: &HashMap<&str, Version>) -> Result<Option<Edit>> {
+    let original = fs::read_to_string(path)?;
+    let mut document: Document = original.parse()?;
+    let packages = document
+        .get_mut("package")
+        .and_then(Item::as_array_of_tables_mut)
+        .ok_or_else(|| anyhow!("{path} has no [[package]] entries"))?;
+    // Dependency is referred as `name version`, when package with the same name
+    // is also resolved from registry
+    let mut references = HashMap::new();
+    for package in packages.iter_mut() {
+        // Registry and git packages have source, workspace ones don't
+        if package.contains_key("source") {
+            continue;
+        }
+        let (name, version) = match package.get("name").and_then(Item::as_str) {
+            Some(name) => match versions.get(name) {
+                Some(version) => (name.to_owned(), version),
+                None => continue,
+            },
+            None => continue,
+        };
+        if let Some(old) = package.get("version").and_then(Item::as_str) {
+            references.insert(format!("{name} {old}"), format!("{name} {version}"));
+        }
+        package.insert("version", toml_edit::value(version.to_string()));
+    }
+    for package in packages.iter_mut() {
+        let dependencies = match package.get_mut("dependencies").and_then(Item::as_array_mut) {
+            Some(dependencies) => dependencies,
+            None => continue,
+        };
+        let updated: Vec<_> = dependencies
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, dependency)| Some((idx, references.get(dependency.as_str()?)?)))
+            .collect();
+        for (idx, reference) in updated {
+            dependencies.replace(idx, reference.as_str());
+        }
+    }
+    let updated = document.to_string();
+    if updated == original {
+        return Ok(None);
+    }
+    Ok(Some(Edit {
+        path: path.to_owned(),
+        old: Some(original),
+        new: updated,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use git2::Signature;
+
+    use super::*;
+
+    /// Write `files` into working tree of `repo`, and commit them on top of HEAD
+    fn commit(repo: &Repository, files: &[(&str, &str)]) -> Oid {
+        let workdir = repo.workdir().expect("repository is not bare");
+        let mut index = repo.index().unwrap();
+        for (path, content) in files {
+            let full = workdir.join(path);
+            fs::create_dir_all(full.parent().unwrap()).unwrap();
+            fs::write(&full, content).unwrap();
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "commit",
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn open(text: &str) -> (tempfile::TempDir, Manifest) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("Cargo.toml")).unwrap();
+        fs::write(&path, text).unwrap();
+        let manifest = Manifest::open(&path, &Edits::default()).unwrap();
+        (dir, manifest)
+    }
+
+    #[test]
+    fn virtual_manifest() {
+        let (_dir, mut manifest) =
+            open("[workspace]\nmembers = [\"a\"]\n\n[workspace.package]\nversion = \"1.0.0\"\n");
+        assert!(!manifest.inherits_version());
+        let error = manifest.set_version(&Version::new(1, 1, 0)).unwrap_err();
+        assert!(error.to_string().contains("has no [package] table"));
+        assert!(manifest.edit().is_none());
+
+        manifest
+            .set_workspace_version(&Version::new(1, 1, 0))
+            .unwrap();
+        assert_eq!(
+            manifest.edit().unwrap().new,
+            "[workspace]\nmembers = [\"a\"]\n\n[workspace.package]\nversion = \"1.1.0\"\n"
+        );
+    }
+
+    #[test]
+    fn workspace_version_formatting() {
+        let text = "[workspace.package]\nversion   =   \"1.0.0\" # shared by all members\n";
+        let (_dir, mut manifest) = open(text);
+        manifest
+            .set_workspace_version(&Version::new(1, 0, 0))
+            .unwrap();
+        assert!(manifest.edit().is_none());
+        manifest
+            .set_workspace_version(&Version::new(1, 1, 0))
+            .unwrap();
+        assert_eq!(
+            manifest.edit().unwrap().new,
+            "[workspace.package]\nversion   =   \"1.1.0\" # shared by all members\n"
+        );
+    }
+
+    #[test]
+    fn package_without_workspace_package() {
+        let (_dir, mut manifest) = open("[package]\nname = \"a\"\nversion.workspace = true\n");
+        assert!(manifest.inherits_version());
+        let error = manifest
+            .set_workspace_version(&Version::new(1, 1, 0))
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("has no [workspace.package] table"));
+    }
+
+    #[test]
+    fn version_at_release_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let released = commit(
+            &repo,
+            &[
+                (
+                    "Cargo.toml",
+                    "[workspace]\nmembers = [\"a\", \"b\"]\n\n[workspace.package]\nversion = \"2.0.0\"\n",
+                ),
+                ("a/Cargo.toml", "[package]\nname = \"a\"\nversion = \"1.0.0\"\n"),
+                (
+                    "b/Cargo.toml",
+                    "[package]\nname = \"b\"\nversion.workspace = true\n",
+                ),
+            ],
+        );
+        // Manifest bumped by --execute, which failed before tagging
+        commit(
+            &repo,
+            &[(
+                "a/Cargo.toml",
+                "[package]\nname = \"a\"\nversion = \"1.1.0\"\n",
+            )],
+        );
+
+        let root = Utf8Path::new("Cargo.toml");
+        let version =
+            |manifest| version_at(&repo, released, Utf8Path::new(manifest), root).unwrap();
+        assert_eq!(version("a/Cargo.toml"), Some(Version::new(1, 0, 0)));
+        assert_eq!(version("b/Cargo.toml"), Some(Version::new(2, 0, 0)));
+        assert_eq!(version("c/Cargo.toml"), None);
+    }
+
+    fn bump(req: &str, version: &str) -> Option<String> {
+        bump_requirement(req, &Version::parse(version).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn requirement_operator_is_kept() {
+        assert_eq!(bump("=1.2.3", "1.3.0").as_deref(), Some("=1.3.0"));
+        assert_eq!(bump("~1.2", "1.3.0").as_deref(), Some("~1.3"));
+        assert_eq!(bump("^1", "2.0.0").as_deref(), Some("^2"));
+        assert_eq!(bump("1.2", "2.0.0").as_deref(), Some("2.0"));
+        assert_eq!(bump("1.2.3", "1.3.0-rc.1").as_deref(), Some("1.3.0-rc.1"));
+        assert_eq!(bump("0.1.0", "0.1.1"), None);
+        assert_eq!(bump("^1", "1.5.0"), None);
+        assert_eq!(bump("1.2", "1.3.0"), None);
+    }
+
+    #[test]
+    fn range_requirement_is_not_updated() {
+        let error = bump_requirement(">=1, <2", &Version::new(2, 0, 0)).unwrap_err();
+        assert!(error.to_string().contains("multi-comparator"));
+        let error = bump_requirement(">=1", &Version::new(0, 5, 0)).unwrap_err();
+        assert!(error.to_string().contains("range operator"));
+    }
+
+    const LOCKFILE: &str = r#"version = 3
+
+[[package]]
+name = "a"
+version = "1.0.0"
+dependencies = [
+ "b 0.1.0",
+ "serde",
+]
+
+[[package]]
+name = "b"
+version = "0.1.0"
+
+[[package]]
+name = "b"
+version = "0.2.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "c"
+version = "1.0.0"
+dependencies = [
+ "b 0.2.0",
+]
+"#;
+
+    fn update(versions: &[(&str, Version)]) -> Option<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("Cargo.lock")).unwrap();
+        fs::write(&path, LOCKFILE).unwrap();
+        let versions = versions.iter().cloned().collect();
+        update_lockfile(&path, &versions)
+            .unwrap()
+            .map(|edit| edit.new)
+    }
+
+    #[test]
+    fn lockfile_versions() {
+        let updated = update(&[("a", Version::new(1, 1, 0)), ("b", Version::new(0, 1, 1))]);
+        assert_eq!(
+            updated.unwrap(),
+            LOCKFILE
+                .replacen("version = \"1.0.0\"", "version = \"1.1.0\"", 1)
+                .replace("version = \"0.1.0\"", "version = \"0.1.1\"")
+                .replace("\"b 0.1.0\"", "\"b 0.1.1\"")
+        );
+        assert_eq!(update(&[("a", Version::new(1, 0, 0))]), None);
+        assert_eq!(update(&[("serde", Version::new(2, 0, 0))]), None);
+    }
+}
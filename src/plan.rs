@@ -0,0 +1,176 @@
+//! Dry-run plan rendering
+
+use std::fmt::{self, Write as _};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    bump::Bump,
+    changelog,
+    generator::{self, ChangelogEntry},
+    PackageStatus,
+};
+
+#[derive(Serialize)]
+struct Summary {
+    packages: usize,
+    changed: usize,
+    major: usize,
+    minor: usize,
+    patch: usize,
+}
+impl Summary {
+    fn new(packages: &[PackageStatus<'_>]) -> Self {
+        let bumps = |bump| packages.iter().filter(|p| p.bump == bump).count();
+        Self {
+            packages: packages.len(),
+            changed: packages.iter().filter(|p| is_changed(p)).count(),
+            major: bumps(Bump::Major),
+            minor: bumps(Bump::Minor),
+            patch: bumps(Bump::Patch),
+        }
+    }
+}
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} packages changed", self.changed, self.packages)?;
+        let bumps: Vec<_> = [
+            (self.major, "major"),
+            (self.minor, "minor"),
+            (self.patch, "patch"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| format!("{count} {kind}"))
+        .collect();
+        if !bumps.is_empty() {
+            write!(f, "; {} bump", bumps.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn is_changed(package: &PackageStatus<'_>) -> bool {
+    package.changelog.trim() != "" || package.is_bumped()
+}
+
+/// Built-in plan template, used when generator has no `planHandler`.
+/// Changes section is omitted with `no_changelog`, as nothing is appended then
+pub fn markdown(
+    packages: &[PackageStatus<'_>],
+    truncated: bool,
+    no_changelog: bool,
+) -> Result<String> {
+    let mut out = String::new();
+    write!(out, "{}\n\n", Summary::new(packages))?;
+    if truncated {
+        write!(
+            out,
+            "**Warning:** history walk was stopped by --limit, changelog is incomplete\n\n"
+        )?;
+    }
+    write!(
+        out,
+        "Hey, seems like you need to have changelog and version bumps for your PR?\n\nDon't worry, i've got you covered, if you have proper commit messages, then changelog generated by me should be okay for you\n\n"
+    )?;
+
+    if !no_changelog {
+        write!(out, "# Changes\n\n")?;
+        write!(
+            out,
+            "After your confirmation, I will append the following entries to changelogs of packages:\n\n"
+        )?;
+        for package in packages {
+            if package.changelog.trim() == "" {
+                continue;
+            }
+            write!(
+                out,
+                "## {} v{} ({:?} bump)\n\n",
+                package.package.name(),
+                package.final_version,
+                package.bump
+            )?;
+            out.push_str(&changelog::demote_headings(package.changelog.trim()));
+        }
+        write!(out, "\n\n")?;
+    }
+    write!(out, "# Bumps\n\n")?;
+    write!(
+        out,
+        "I may not be able to describe reason for bump, but they should be required:\n\n"
+    )?;
+    for package in packages {
+        if !package.is_bumped() {
+            continue;
+        }
+        write!(
+            out,
+            "{} `{}` -> `{}`\n\n",
+            package.package.name(),
+            package.package.version(),
+            package.final_version
+        )?;
+        for reason in &package.bump_reasons {
+            write!(out, "- {}\n\n", reason)?;
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Serialize)]
+struct PlannedPackage<'a> {
+    name: &'a str,
+    current_version: String,
+    final_version: String,
+    bump: Bump,
+    bump_reasons: &'a [String],
+    changelog: &'a str,
+    entries: &'a [ChangelogEntry],
+}
+
+#[derive(Serialize)]
+struct Plan<'a> {
+    summary: Summary,
+    /// History walk was stopped by `--limit`
+    truncated: bool,
+    packages: Vec<PlannedPackage<'a>>,
+}
+
+/// Packages without changelog are only listed when they are bumped
+pub fn json(packages: &[PackageStatus<'_>], truncated: bool) -> Result<String> {
+    let planned: Vec<_> = packages
+        .iter()
+        .filter(|package| is_changed(package))
+        .map(|package| PlannedPackage {
+            name: package.package.name(),
+            current_version: package.package.version().to_string(),
+            final_version: package.final_version.to_string(),
+            bump: package.bump,
+            bump_reasons: &package.bump_reasons,
+            changelog: package.changelog.trim(),
+            entries: &package.entries,
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&Plan {
+        summary: Summary::new(packages),
+        truncated,
+        packages: planned,
+    })?)
+}
+
+/// Input of generator `planHandler`, contains all packages
+pub fn generator_input(packages: &[PackageStatus<'_>]) -> Vec<generator::PlannedPackage> {
+    packages
+        .iter()
+        .map(|package| generator::PlannedPackage {
+            name: package.package.name().to_owned(),
+            current_version: package.package.version().to_string(),
+            final_version: package.final_version.to_string(),
+            bump: format!("{:?}", package.bump).to_lowercase(),
+            bump_reasons: package.bump_reasons.clone(),
+            changelog: package.changelog.trim().to_owned(),
+        })
+        .collect()
+}
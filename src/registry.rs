@@ -0,0 +1,51 @@
+//! Published versions from crates.io sparse index
+
+use anyhow::{anyhow, Result};
+use semver::Version;
+use serde::Deserialize;
+use tracing::info;
+
+const INDEX: &str = "https://index.crates.io";
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    vers: String,
+}
+
+/// Path of crate in index, see <https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files>
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Highest version of crate `name` ever published, including yanked ones,
+/// as they can't be published again. `None` if crate is not published
+pub fn latest_version(name: &str) -> Result<Option<Version>> {
+    info!("fetching published versions of {name}");
+    let response = match ureq::get(&format!("{INDEX}/{}", index_path(name)))
+        .set("User-Agent", "bureaucrate")
+        .call()
+    {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404 | 410 | 451, _)) => return Ok(None),
+        Err(e) => return Err(anyhow!("failed to fetch {name} from crates.io index: {e}")),
+    };
+    let mut latest: Option<Version> = None;
+    for line in response.into_string()?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: IndexEntry = serde_json::from_str(line)
+            .map_err(|e| anyhow!("invalid crates.io index entry of {name}: {e}"))?;
+        let version = Version::parse(&entry.vers)?;
+        if !matches!(&latest, Some(latest) if latest >= &version) {
+            latest = Some(version);
+        }
+    }
+    Ok(latest)
+}
@@ -0,0 +1,87 @@
+//! Recording executed changes in git
+
+use anyhow::{anyhow, Result};
+use camino::Utf8PathBuf;
+use git2::{BranchType, ErrorCode, Oid, Repository};
+use semver::Version;
+
+/// Stage `paths` and commit them on top of HEAD
+pub fn commit(repo: &Repository, paths: &[Utf8PathBuf], message: &str) -> Result<Oid> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("can't commit in bare repository"))?
+        .canonicalize()?;
+    let mut index = repo.index()?;
+    for path in paths {
+        let path = path.as_std_path().canonicalize()?;
+        let relative = path
+            .strip_prefix(&workdir)
+            .map_err(|_| anyhow!("{} is outside of repository", path.display()))?;
+        index.add_path(relative)?;
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = repo.signature()?;
+    let parent = repo.head()?.peel_to_commit()?;
+    Ok(repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )?)
+}
+
+/// Create branch `name` at HEAD and switch to it, so release commit lands there.
+///
+/// Existing branch is only reused if it points at HEAD, with `force` it is reset to HEAD
+pub fn switch_branch(repo: &Repository, name: &str, force: bool) -> Result<()> {
+    let head = repo.head()?.peel_to_commit()?;
+    match repo.find_branch(name, BranchType::Local) {
+        Ok(branch) => {
+            let target = branch.get().peel_to_commit()?.id();
+            if target != head.id() && !force {
+                return Err(anyhow!(
+                    "branch {name} already exists and points at {target} instead of HEAD ({}), pass --force-branch to reset it",
+                    head.id()
+                ));
+            }
+            if target != head.id() {
+                repo.branch(name, &head, true)?;
+            }
+        }
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            repo.branch(name, &head, false)?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    // Branch points at HEAD, so working tree and index are already up to date
+    repo.set_head(&format!("refs/heads/{name}"))?;
+    Ok(())
+}
+
+/// Name of release tag, created by [`tag`]
+pub fn tag_name(prefix: &str, version: &Version) -> String {
+    format!("{prefix}{version}")
+}
+
+/// Create annotated `<prefix><version>` tag pointing at `target`
+pub fn tag(
+    repo: &Repository,
+    target: Oid,
+    prefix: &str,
+    name: &str,
+    version: &Version,
+) -> Result<()> {
+    let target = repo.find_object(target, None)?;
+    let signature = repo.signature()?;
+    repo.tag(
+        &tag_name(prefix, version),
+        &target,
+        &signature,
+        &format!("{name} v{version}"),
+        false,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,85 @@
+//! Links to git hosting web interface
+
+use git2::Repository;
+use tracing::warn;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Host {
+    GitHub,
+    GitLab,
+}
+
+/// Web interface of repository, i.e `https://github.com/org/repo`
+#[derive(Debug)]
+pub struct Remote {
+    host: Host,
+    base: String,
+}
+impl Remote {
+    /// Parse both SSH (`git@host:org/repo.git`, `ssh://git@host/org/repo`)
+    /// and HTTPS remote URLs, returns `None` for unsupported hosts
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim().trim_end_matches('/');
+        let url = url.strip_suffix(".git").unwrap_or(url);
+        let rest = if let Some((_, rest)) = url.split_once("://") {
+            // Drop userinfo and port
+            let rest = rest.rsplit_once('@').map_or(rest, |(_, r)| r);
+            let (host, path) = rest.split_once('/')?;
+            let host = host.split_once(':').map_or(host, |(h, _)| h);
+            format!("{host}/{path}")
+        } else {
+            // scp-like syntax
+            let rest = url.rsplit_once('@').map_or(url, |(_, r)| r);
+            let (host, path) = rest.split_once(':')?;
+            format!("{host}/{}", path.trim_start_matches('/'))
+        };
+        let host_name = rest.split('/').next()?;
+        let host = if host_name == "github.com" {
+            Host::GitHub
+        } else if host_name == "gitlab.com" || host_name.starts_with("gitlab.") {
+            Host::GitLab
+        } else {
+            return None;
+        };
+        Some(Self {
+            host,
+            base: format!("https://{rest}"),
+        })
+    }
+
+    /// Reads URL of remote `name`, failing softly, as links are optional
+    pub fn find(repo: &Repository, name: &str) -> Option<Self> {
+        let remote = match repo.find_remote(name) {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("remote {name} not found, links will not be generated: {e}");
+                return None;
+            }
+        };
+        let url = remote.url()?;
+        let parsed = Self::parse(url);
+        if parsed.is_none() {
+            warn!("unsupported remote url {url}, links will not be generated");
+        }
+        parsed
+    }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    fn separator(&self) -> &'static str {
+        match self.host {
+            Host::GitHub => "",
+            Host::GitLab => "/-",
+        }
+    }
+
+    pub fn commit_url(&self, id: &str) -> String {
+        format!("{}{}/commit/{id}", self.base, self.separator())
+    }
+
+    pub fn compare_url(&self, from: &str, to: &str) -> String {
+        format!("{}{}/compare/{from}...{to}", self.base, self.separator())
+    }
+}
@@ -0,0 +1,37 @@
+//! Breaking API change detection using `cargo-semver-checks`
+
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8Path;
+use git2::Oid;
+use tracing::info;
+
+/// Message printed by `cargo-semver-checks`, when it found breaking changes
+const BREAKING_MARKER: &str = "requires new major version";
+
+/// Compare public API of package at `manifest_path` with `baseline` revision,
+/// or with the latest version published to registry, when there is none
+pub fn is_breaking(manifest_path: &Utf8Path, name: &str, baseline: Option<Oid>) -> Result<bool> {
+    info!("running cargo-semver-checks for {name}");
+    let mut command = Command::new("cargo");
+    command
+        .args(["semver-checks", "check-release", "--package", name])
+        .arg("--manifest-path")
+        .arg(manifest_path);
+    if let Some(baseline) = baseline {
+        command.arg("--baseline-rev").arg(baseline.to_string());
+    }
+    let output = command
+        .output()
+        .context("failed to run cargo-semver-checks, is it installed?")?;
+    if output.status.success() {
+        return Ok(false);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stdout.contains(BREAKING_MARKER) || stderr.contains(BREAKING_MARKER) {
+        return Ok(true);
+    }
+    Err(anyhow!("cargo-semver-checks failed for {name}:\n{stderr}"))
+}
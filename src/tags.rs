@@ -0,0 +1,44 @@
+//! Release tag lookup
+
+use anyhow::Result;
+use git2::{Oid, Repository};
+use semver::Version;
+
+/// Literal part of tag glob pattern, i.e `v` for `v*`
+fn pattern_prefix(pattern: &str) -> &str {
+    let end = pattern.find(['*', '?', '[']).unwrap_or(pattern.len());
+    &pattern[..end]
+}
+
+/// Finds tag with highest version, which matches glob `pattern` and is reachable from `head`.
+///
+/// Version is parsed from the tag name after the literal prefix of pattern,
+/// tags which aren't valid semver are skipped
+pub fn latest_tag(repo: &Repository, pattern: &str, head: Oid) -> Result<Option<(Version, Oid)>> {
+    let prefix = pattern_prefix(pattern);
+    let mut latest: Option<(Version, Oid)> = None;
+    for name in repo.tag_names(Some(pattern))?.iter().flatten() {
+        let version = match name
+            .strip_prefix(prefix)
+            .and_then(|v| Version::parse(v).ok())
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let commit = repo
+            .revparse_single(&format!("refs/tags/{name}"))?
+            .peel_to_commit()?
+            .id();
+        if commit != head && !repo.graph_descendant_of(head, commit)? {
+            continue;
+        }
+        let is_newer = match &latest {
+            Some((latest, _)) => latest < &version,
+            None => true,
+        };
+        if is_newer {
+            latest = Some((version, commit));
+        }
+    }
+    Ok(latest)
+}
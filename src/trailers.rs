@@ -0,0 +1,66 @@
+//! Commit message trailers (`Key: value` lines in the last paragraph)
+
+use std::collections::BTreeMap;
+
+use crate::generator::{CoAuthor, Trailers};
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Trailers in order of appearance, continuation lines (starting with whitespace)
+/// are folded into the previous value.
+///
+/// Last paragraph is only considered a trailer block if every line of it is
+/// either trailer or continuation, and it isn't the title paragraph
+pub fn parse(message: &str) -> Vec<(String, String)> {
+    let message = message.trim_end();
+    let block = match message.rfind("\n\n") {
+        Some(offset) => &message[offset + 2..],
+        None => return vec![],
+    };
+    let mut trailers: Vec<(String, String)> = vec![];
+    for line in block.lines() {
+        if line.starts_with([' ', '\t']) {
+            match trailers.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                    continue;
+                }
+                None => return vec![],
+            }
+        }
+        match line.split_once(": ") {
+            Some((key, value)) if is_key(key) => {
+                trailers.push((key.to_owned(), value.trim().to_owned()));
+            }
+            _ => return vec![],
+        }
+    }
+    trailers
+}
+
+/// People from `Co-authored-by: Name <email>` trailers
+pub fn co_authors(trailers: &[(String, String)]) -> Vec<CoAuthor> {
+    trailers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("Co-authored-by"))
+        .filter_map(|(_, value)| {
+            let (name, email) = value.split_once('<')?;
+            Some(CoAuthor {
+                name: name.trim().to_owned(),
+                email: email.trim_end().strip_suffix('>')?.trim().to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Group trailer values by key
+pub fn to_map(trailers: &[(String, String)]) -> Trailers {
+    let mut map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, value) in trailers {
+        map.entry(key.clone()).or_default().push(value.clone());
+    }
+    Trailers(map)
+}